@@ -0,0 +1,96 @@
+use bevy::{ecs::system::EntityCommand, prelude::*, reflect::TypeRegistry};
+use bevy_ggrs::{__bench, AddRollbackCommand};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+const ENTITY_COUNTS: [usize; 3] = [1_000, 10_000, 100_000];
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+struct Position {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+struct Velocity {
+    x: f32,
+    y: f32,
+}
+
+/// Not registered for rollback, only splits the entities into more archetypes
+#[derive(Component)]
+struct Unregistered;
+
+fn type_registry() -> TypeRegistry {
+    let type_registry = TypeRegistry::default();
+    {
+        let mut registry = type_registry.write();
+        registry.register::<Position>();
+        registry.register::<Velocity>();
+    }
+    type_registry
+}
+
+/// Creates a world with `count` rollback entities, spread over a few archetypes, and as many
+/// entities without `Rollback`.
+fn setup_world(count: usize) -> World {
+    let mut world = World::new();
+    for i in 0..count {
+        let position = Position { x: i as f32, y: 0. };
+        let entity = match i % 3 {
+            0 => world.spawn(position).id(),
+            1 => world.spawn((position, Velocity::default())).id(),
+            _ => world
+                .spawn((position, Velocity::default(), Unregistered))
+                .id(),
+        };
+        AddRollbackCommand.apply(entity, &mut world);
+        world.spawn((Position::default(), Unregistered));
+    }
+    world
+}
+
+fn save(c: &mut Criterion) {
+    let type_registry = type_registry();
+    let mut group = c.benchmark_group("save");
+    for count in ENTITY_COUNTS {
+        let world = setup_world(count);
+        let tick = world.read_change_tick();
+        group.bench_with_input(BenchmarkId::from_parameter(count), &world, |b, world| {
+            b.iter(|| __bench::save(world, &type_registry, tick))
+        });
+    }
+    group.finish();
+}
+
+/// Creates the same world as `setup_world`, but with all positions moved, so loading a snapshot
+/// of the original world has to restore every position.
+fn setup_mutated_world(count: usize) -> World {
+    let mut world = setup_world(count);
+    for mut position in world.query::<&mut Position>().iter_mut(&mut world) {
+        position.y += 1.;
+    }
+    world
+}
+
+fn load(c: &mut Criterion) {
+    let type_registry = type_registry();
+    let mut group = c.benchmark_group("load");
+    for count in ENTITY_COUNTS {
+        let world = setup_world(count);
+        let tick = world.read_change_tick();
+        let snapshot = __bench::save(&world, &type_registry, tick);
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter_batched_ref(
+                || setup_mutated_world(count),
+                |world| __bench::load(&snapshot, world, &type_registry, tick),
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, save, load);
+criterion_main!(benches);
@@ -0,0 +1,311 @@
+use bevy::{prelude::*, transform::TransformSystem};
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// A signed 16.16 fixed-point number. All arithmetic is done on integers, so the results are
+/// identical on all platforms, and unlike floats it supports `reflect_hash`, so it is included in
+/// the snapshot checksums.
+///
+/// Overflow wraps around in all builds, like the `wrapping_*` methods of `i32`, so peers running
+/// debug and release builds stay in sync instead of only some of them panicking.
+#[derive(Reflect, Default, Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[reflect(Hash)]
+pub struct Fixed(i32);
+
+impl Fixed {
+    /// The number of fractional bits.
+    pub const FRAC_BITS: u32 = 16;
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(1 << Self::FRAC_BITS);
+    pub const MIN: Self = Self(i32::MIN);
+    pub const MAX: Self = Self(i32::MAX);
+
+    /// Creates a number from its raw representation.
+    pub const fn from_bits(bits: i32) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the raw representation of the number.
+    pub const fn to_bits(self) -> i32 {
+        self.0
+    }
+
+    /// Creates a number from an integer.
+    pub const fn from_int(value: i32) -> Self {
+        Self(value << Self::FRAC_BITS)
+    }
+
+    /// Creates the number closest to `numerator / denominator`, rounded towards zero. Results
+    /// outside of the range of `Fixed` wrap around.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `denominator` is zero.
+    pub const fn from_ratio(numerator: i32, denominator: i32) -> Self {
+        Self(((numerator as i64) << Self::FRAC_BITS).wrapping_div(denominator as i64) as i32)
+    }
+
+    /// Creates the number closest to `value`. The conversion itself is deterministic, but the
+    /// input is only identical on all peers if it is, e.g. a constant.
+    pub fn from_f32(value: f32) -> Self {
+        Self((value * Self::ONE.0 as f32).round() as i32)
+    }
+
+    /// Converts the number to a float, e.g. for rendering. Don't feed the result back into the
+    /// simulation.
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / Self::ONE.0 as f32
+    }
+
+    /// Returns the largest integer less than or equal to the number.
+    pub const fn floor(self) -> i32 {
+        self.0 >> Self::FRAC_BITS
+    }
+
+    /// Returns the absolute value of the number. The absolute value of `Fixed::MIN` wraps around to
+    /// `Fixed::MIN`.
+    pub const fn abs(self) -> Self {
+        Self(self.0.wrapping_abs())
+    }
+
+    /// Returns `-1`, `0` or `1` depending on the sign of the number.
+    pub const fn signum(self) -> Self {
+        Self::from_int(self.0.signum())
+    }
+
+    /// Returns the square root of the number, rounded down. Negative numbers return zero.
+    pub fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Self::ZERO;
+        }
+        // the root of the raw value, shifted by the fractional bits once more
+        let value = (self.0 as u64) << Self::FRAC_BITS;
+        let mut root = 0u64;
+        let mut bit = 1u64 << 62;
+        while bit > value {
+            bit >>= 2;
+        }
+        let mut rest = value;
+        while bit != 0 {
+            if rest >= root + bit {
+                rest -= root + bit;
+                root = (root >> 1) + bit;
+            } else {
+                root >>= 1;
+            }
+            bit >>= 2;
+        }
+        Self(root as i32)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        // the cast truncates, so results outside of the range wrap around
+        Self(((self.0 as i64).wrapping_mul(rhs.0 as i64) >> Self::FRAC_BITS) as i32)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        // the cast truncates, so results outside of the range wrap around
+        Self(((self.0 as i64) << Self::FRAC_BITS).wrapping_div(rhs.0 as i64) as i32)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(self.0.wrapping_neg())
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign for Fixed {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+impl DivAssign for Fixed {
+    fn div_assign(&mut self, rhs: Self) {
+        *self = *self / rhs;
+    }
+}
+
+/// A three-dimensional vector of `Fixed` numbers.
+#[derive(Reflect, Default, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[reflect(Hash)]
+pub struct FixedVec3 {
+    pub x: Fixed,
+    pub y: Fixed,
+    pub z: Fixed,
+}
+
+impl FixedVec3 {
+    pub const ZERO: Self = Self::new(Fixed::ZERO, Fixed::ZERO, Fixed::ZERO);
+
+    /// Creates a new vector.
+    pub const fn new(x: Fixed, y: Fixed, z: Fixed) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Creates the vector closest to `value`, see `Fixed::from_f32()`.
+    pub fn from_vec3(value: Vec3) -> Self {
+        Self::new(
+            Fixed::from_f32(value.x),
+            Fixed::from_f32(value.y),
+            Fixed::from_f32(value.z),
+        )
+    }
+
+    /// Converts the vector to floats, e.g. for rendering.
+    pub fn to_vec3(self) -> Vec3 {
+        Vec3::new(self.x.to_f32(), self.y.to_f32(), self.z.to_f32())
+    }
+
+    /// Returns the dot product of two vectors.
+    pub fn dot(self, rhs: Self) -> Fixed {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    /// Returns the squared length of the vector.
+    pub fn length_squared(self) -> Fixed {
+        self.dot(self)
+    }
+
+    /// Returns the length of the vector.
+    pub fn length(self) -> Fixed {
+        self.length_squared().sqrt()
+    }
+}
+
+impl Add for FixedVec3 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl Sub for FixedVec3 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Mul<Fixed> for FixedVec3 {
+    type Output = Self;
+
+    fn mul(self, rhs: Fixed) -> Self {
+        Self::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl Div<Fixed> for FixedVec3 {
+    type Output = Self;
+
+    fn div(self, rhs: Fixed) -> Self {
+        Self::new(self.x / rhs, self.y / rhs, self.z / rhs)
+    }
+}
+
+impl Neg for FixedVec3 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl AddAssign for FixedVec3 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for FixedVec3 {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign<Fixed> for FixedVec3 {
+    fn mul_assign(&mut self, rhs: Fixed) {
+        *self = *self * rhs;
+    }
+}
+
+/// A deterministic translation for rollback entities. Use it instead of `Transform` in the
+/// rollback schedule, so positions are identical on all peers and part of the snapshot checksums.
+///
+/// The component is registered for rollback by default. Add the `RollbackTransformPlugin` to copy
+/// it into the `Transform` of the entity for rendering.
+///
+/// Only the translation is covered, rotation and scale of the `Transform` are left untouched and
+/// have to be registered separately if they are part of the game state. The box_game examples still
+/// register `Transform` itself, since they move the cubes with floats.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[reflect(Component, Hash)]
+pub struct RollbackTransform {
+    pub translation: FixedVec3,
+}
+
+impl RollbackTransform {
+    /// Creates a new transform with the given translation.
+    pub fn from_translation(translation: FixedVec3) -> Self {
+        Self { translation }
+    }
+}
+
+/// Copies the translation of every `RollbackTransform` into the `Transform` of the same entity,
+/// before the transforms are propagated.
+pub struct RollbackTransformPlugin;
+
+impl Plugin for RollbackTransformPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            sync_rollback_transforms.before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+fn sync_rollback_transforms(mut query: Query<(&RollbackTransform, &mut Transform)>) {
+    for (rollback_transform, mut transform) in query.iter_mut() {
+        transform.translation = rollback_transform.translation.to_vec3();
+    }
+}
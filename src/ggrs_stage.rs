@@ -1,9 +1,96 @@
-use crate::{world_snapshot::WorldSnapshot, GgrsSchedule, PlayerInputs, Session};
-use bevy::{prelude::*, reflect::TypeRegistry};
+use crate::{
+    removal::{rollback_id, SavedRemovals},
+    rollback::{RollbackFilter, RollbackFrames, RollbackIdProvider},
+    state::{RollbackState, SavedState},
+    time::tick_timers,
+    world_snapshot::WorldSnapshot,
+    ConfirmedFrameCount, GgrsSchedule, GgrsUpdateInfo, HookSchedules, InputHistory, LocalInputs,
+    PendingRollbackDespawn, PlayerInputs, PostUpdatePending, Rollback, RollbackFrameCount,
+    RollbackRemovals, RollbackTime, Session,
+};
+use bevy::{
+    ecs::{
+        component::{ComponentId, Tick},
+        event::ManualEventReader,
+        removal_detection::RemovedComponentEntity,
+        schedule::BoxedScheduleLabel,
+    },
+    hierarchy::despawn_with_children_recursive,
+    prelude::*,
+    reflect::TypeRegistry,
+    utils::{HashMap, HashSet},
+};
+use bytemuck::Zeroable;
 use ggrs::{
-    Config, GGRSError, GGRSRequest, GameStateCell, InputStatus, PlayerHandle, SessionState,
+    Config, Frame, GGRSError, GGRSRequest, GameStateCell, InputStatus, PlayerHandle, SessionState,
 };
 use instant::{Duration, Instant};
+use std::any::TypeId;
+
+/// A system that is run for every entity with the `marker` component that has been respawned by a
+/// rollback, see `GgrsPlugin::register_respawn_system()`.
+pub(crate) struct RespawnSystem {
+    pub(crate) marker: TypeId,
+    pub(crate) system: Box<dyn System<In = Entity, Out = ()>>,
+}
+
+/// The different ways of collecting local inputs that can be registered with the `GgrsPlugin`.
+pub(crate) enum InputSystem<T: Config> {
+    /// Runs once per local player, see `GgrsPlugin::with_input_system()`
+    PerPlayer(Box<dyn System<In = PlayerHandle, Out = T::Input>>),
+    /// Runs once for all local players, see `GgrsPlugin::with_batch_input_system()`
+    Batch(Box<dyn System<In = (Vec<PlayerHandle>, Frame), Out = Vec<T::Input>>>),
+    /// Reads the `LocalInputs<T>` resource, see `GgrsPlugin::with_local_inputs()`
+    Resource,
+}
+
+impl<T: Config> InputSystem<T> {
+    pub(crate) fn initialize(&mut self, world: &mut World) {
+        match self {
+            InputSystem::PerPlayer(system) => system.initialize(world),
+            InputSystem::Batch(system) => system.initialize(world),
+            InputSystem::Resource => world.init_resource::<LocalInputs<T>>(),
+        }
+    }
+
+    /// Returns the inputs of the given local players, in the same order as the handles.
+    pub(crate) fn run(
+        &mut self,
+        handles: Vec<PlayerHandle>,
+        frame: Frame,
+        world: &mut World,
+    ) -> Vec<T::Input> {
+        match self {
+            InputSystem::PerPlayer(system) => handles
+                .into_iter()
+                .map(|handle| system.run(handle, world))
+                .collect(),
+            InputSystem::Batch(system) => {
+                let num_handles = handles.len();
+                let inputs = system.run((handles, frame), world);
+                assert_eq!(
+                    inputs.len(),
+                    num_handles,
+                    "The batch input system has to return exactly one input per local player handle"
+                );
+                inputs
+            }
+            InputSystem::Resource => {
+                let local_inputs = world.resource::<LocalInputs<T>>();
+                handles
+                    .into_iter()
+                    .map(|handle| match local_inputs.get(&handle) {
+                        Some(&input) => input,
+                        None => {
+                            debug!("No local input for player {handle} found, using zeroed input");
+                            T::Input::zeroed()
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+}
 
 #[derive(Resource)]
 /// The GgrsStage handles updating, saving and loading the game state.
@@ -13,20 +100,48 @@ where
 {
     /// Used to register all types considered when loading and saving
     pub(crate) type_registry: TypeRegistry,
+    /// The schedule that is run to advance the game by a single frame
+    pub(crate) schedule: BoxedScheduleLabel,
+    /// The schedules that are run around saving and loading snapshots
+    pub(crate) hooks: HookSchedules,
+    /// If set, only `Rollback` entities with this marker component are saved and loaded
+    pub(crate) scope: Option<ComponentId>,
+    /// If set, entities respawned by a rollback keep the entity id they had when the snapshot was taken
+    pub(crate) preserve_entity_ids: bool,
     /// This system is used to get an encoded representation of the input that GGRS can handle
-    pub(crate) input_system: Box<dyn System<In = PlayerHandle, Out = T::Input>>,
+    pub(crate) input_system: InputSystem<T>,
+    /// These systems re-attach non-rollback components to entities respawned by a rollback
+    pub(crate) respawn_systems: Vec<RespawnSystem>,
+    /// The states that are saved and loaded alongside the snapshots
+    pub(crate) rollback_states: Vec<RollbackState>,
     /// Instead of using GGRS's internal storage for encoded save states, we save the world here, avoiding serialization into `Vec<u8>`.
     snapshots: Vec<WorldSnapshot>,
+    /// The input history at each saved frame, stored next to the snapshot of that frame
+    input_histories: Vec<Option<InputHistory<T>>>,
+    /// The removals of registered components at each saved frame, stored next to the snapshot of that frame
+    removals: Vec<SavedRemovals>,
+    /// The registered states at each saved frame, stored next to the snapshot of that frame
+    states: Vec<Vec<SavedState>>,
+    /// Readers of the removal events of registered components
+    removal_readers: HashMap<ComponentId, ManualEventReader<RemovedComponentEntity>>,
     /// fixed FPS our logic is running with
     update_frequency: usize,
     /// counts the number of frames that have been executed
     frame: i32,
+    /// the most recent frame that will not be rolled back anymore
+    confirmed_frame: i32,
     /// internal time control variables
     last_update: Instant,
     /// accumulated time. once enough time has been accumulated, an update is executed
     accumulator: Duration,
     /// boolean to see if we should run slow to let remote clients catch up
     run_slow: bool,
+    /// unregistered component types we already warned about, so every type is only reported once
+    unregistered_warned: HashSet<ComponentId>,
+    /// the change tick at the start of the last advanced frame
+    last_frame_tick: Tick,
+    /// the frames advanced during the current update
+    update_info: GgrsUpdateInfo<T>,
 }
 
 impl<T: Config + Send + Sync> GgrsStage<T> {
@@ -44,6 +159,8 @@ impl<T: Config + Send + Sync> GgrsStage<T> {
         stage.accumulator = stage.accumulator.saturating_add(delta);
         stage.last_update = Instant::now();
 
+        stage.update_info = GgrsUpdateInfo::default();
+
         // no matter what, poll remotes and send responses
         if let Some(mut session) = world.get_resource_mut::<Session<T>>() {
             match &mut *session {
@@ -74,21 +191,44 @@ impl<T: Config + Send + Sync> GgrsStage<T> {
             }
         }
 
+        // let the app react to the final state of this update, once all instances have advanced
+        // their frames
+        world.insert_resource(stage.update_info);
+        if stage.update_info.frames_advanced > 0 {
+            world
+                .get_resource_or_insert_with(PostUpdatePending::default)
+                .0 = true;
+        }
+
         world.insert_resource(stage);
     }
 }
 
 impl<T: Config> GgrsStage<T> {
-    pub(crate) fn new(input_system: Box<dyn System<In = PlayerHandle, Out = T::Input>>) -> Self {
+    pub(crate) fn new(input_system: InputSystem<T>) -> Self {
         Self {
             type_registry: TypeRegistry::default(),
+            schedule: Box::new(GgrsSchedule),
+            hooks: HookSchedules::default(),
+            scope: None,
+            preserve_entity_ids: false,
             input_system,
+            respawn_systems: Vec::new(),
+            rollback_states: Vec::new(),
             snapshots: Vec::new(),
+            input_histories: Vec::new(),
+            removals: Vec::new(),
+            states: Vec::new(),
+            removal_readers: HashMap::default(),
             frame: 0,
+            confirmed_frame: -1,
             update_frequency: 60,
             last_update: Instant::now(),
             accumulator: Duration::ZERO,
             run_slow: false,
+            unregistered_warned: HashSet::default(),
+            last_frame_tick: Tick::new(0),
+            update_info: GgrsUpdateInfo::default(),
         }
     }
 
@@ -96,8 +236,12 @@ impl<T: Config> GgrsStage<T> {
         self.last_update = Instant::now();
         self.accumulator = Duration::ZERO;
         self.frame = 0;
+        self.confirmed_frame = -1;
         self.run_slow = false;
         self.snapshots = Vec::new();
+        self.input_histories = Vec::new();
+        self.removals = Vec::new();
+        self.states = Vec::new();
     }
 
     pub(crate) fn run_synctest(&mut self, world: &mut World) {
@@ -114,11 +258,12 @@ impl<T: Config> GgrsStage<T> {
             }
         }
 
+        // synctest sessions never roll back further than the prediction window
+        let max_prediction = sess.max_prediction() as i32;
+
         // get inputs for all players
-        let mut inputs = Vec::new();
-        for handle in 0..sess.num_players() {
-            inputs.push(self.input_system.run(handle, world));
-        }
+        let handles = (0..sess.num_players()).collect();
+        let inputs = self.input_system.run(handles, self.frame, world);
 
         let mut sess = world.get_resource_mut::<Session<T>>();
         let Some(Session::SyncTest(ref mut sess)) = sess.as_deref_mut() else {
@@ -132,6 +277,7 @@ impl<T: Config> GgrsStage<T> {
             Ok(requests) => self.handle_requests(requests, world),
             Err(e) => warn!("{}", e),
         }
+        self.confirm_frame(self.frame - max_prediction, world);
     }
 
     pub(crate) fn run_spectator(&mut self, world: &mut World) {
@@ -151,6 +297,8 @@ impl<T: Config> GgrsStage<T> {
                 }
                 Err(e) => warn!("{}", e),
             };
+            // spectators only advance confirmed frames
+            self.confirm_frame(self.frame, world);
         }
     }
 
@@ -176,11 +324,9 @@ impl<T: Config> GgrsStage<T> {
         let local_handles = sess.local_player_handles();
 
         // get local player inputs
-        let mut local_inputs = Vec::new();
-        for &local_handle in &local_handles {
-            let input = self.input_system.run(local_handle, world);
-            local_inputs.push(input);
-        }
+        let local_inputs = self
+            .input_system
+            .run(local_handles.clone(), self.frame, world);
 
         // if session is ready, try to advance the frame
         let mut sess = world.get_resource_mut::<Session<T>>();
@@ -193,6 +339,7 @@ impl<T: Config> GgrsStage<T> {
                 sess.add_local_input(local_handles[i], local_inputs[i])
                     .expect("All handles in local_handles should be valid");
             }
+            let confirmed_frame = sess.confirmed_frame();
             match sess.advance_frame() {
                 Ok(requests) => self.handle_requests(requests, world),
                 Err(GGRSError::PredictionThreshold) => {
@@ -200,17 +347,29 @@ impl<T: Config> GgrsStage<T> {
                 }
                 Err(e) => warn!("{}", e),
             };
+            self.confirm_frame(confirmed_frame, world);
         }
     }
 
     pub(crate) fn handle_requests(&mut self, requests: Vec<GGRSRequest<T>>, world: &mut World) {
+        let mut rolled_back = false;
         for request in requests {
             match request {
                 GGRSRequest::SaveGameState { cell, frame } => self.save_world(cell, frame, world),
-                GGRSRequest::LoadGameState { frame, .. } => self.load_world(frame, world),
+                GGRSRequest::LoadGameState { frame, .. } => {
+                    self.load_world(frame, world);
+                    rolled_back = true;
+                    self.update_info.rolled_back = true;
+                }
                 GGRSRequest::AdvanceFrame { inputs } => self.advance_frame(inputs, world),
             }
         }
+
+        // the rollback is complete once all requested frames have been simulated again
+        if rolled_back {
+            // the hook schedules only exist if the app added systems to them
+            let _ = world.try_run_schedule(&*self.hooks.post_rollback);
+        }
     }
 
     pub(crate) fn save_world(
@@ -221,9 +380,25 @@ impl<T: Config> GgrsStage<T> {
     ) {
         debug!("saving snapshot for frame {frame}");
         assert_eq!(self.frame, frame);
+        let _ = world.try_run_schedule(&*self.hooks.pre_save);
 
         // we make a snapshot of our world
-        let snapshot = WorldSnapshot::from_world(world, &self.type_registry);
+        let filter = RollbackFilter::new(world, self.scope);
+        let mut snapshot =
+            WorldSnapshot::from_world(world, &self.type_registry, &filter, self.last_frame_tick);
+        snapshot.next_rollback_id = world
+            .get_resource::<RollbackIdProvider>()
+            .map_or(0, |provider| provider.next_id(self.scope));
+
+        // states are not reflected, so they are saved and added to the checksum separately
+        let states: Vec<SavedState> = self
+            .rollback_states
+            .iter()
+            .map(|rollback_state| rollback_state.save(world))
+            .collect();
+        for state in states.iter() {
+            snapshot.checksum = snapshot.checksum.wrapping_add(state.hash);
+        }
 
         // we don't really use the buffer provided by GGRS
         cell.save(self.frame, None, Some(snapshot.checksum as u128));
@@ -231,18 +406,81 @@ impl<T: Config> GgrsStage<T> {
         // store the snapshot ourselves (since the snapshots don't implement clone)
         let pos = frame as usize % self.snapshots.len();
         self.snapshots[pos] = snapshot;
+
+        // the input history is not reflected, so we store a copy of it ourselves
+        if let Some(history) = world.get_resource::<InputHistory<T>>() {
+            self.input_histories
+                .resize_with(self.snapshots.len(), || None);
+            self.input_histories[pos] = Some(history.clone());
+        }
+
+        self.removals
+            .resize_with(self.snapshots.len(), SavedRemovals::default);
+        if let Some(removals) = world.get_resource::<RollbackRemovals<T>>() {
+            self.removals[pos] = removals.save(world, &filter);
+        }
+
+        self.states.resize_with(self.snapshots.len(), Vec::new);
+        self.states[pos] = states;
     }
 
     pub(crate) fn load_world(&mut self, frame: i32, world: &mut World) {
         debug!("restoring snapshot for frame {frame}");
+        let _ = world.try_run_schedule(&*self.hooks.pre_rollback);
         self.frame = frame;
 
         // we get the correct snapshot
         let pos = frame as usize % self.snapshots.len();
         let snapshot_to_load = &self.snapshots[pos];
 
+        self.set_frame(world, frame);
+
         // load the entities
-        snapshot_to_load.write_to_world(world, &self.type_registry);
+        let filter = RollbackFilter::new(world, self.scope);
+        let respawned = snapshot_to_load.write_to_world(
+            world,
+            &self.type_registry,
+            &filter,
+            frame,
+            self.preserve_entity_ids,
+            self.last_frame_tick,
+        );
+        world
+            .get_resource_or_insert_with(RollbackIdProvider::default)
+            .set_next_id(self.scope, snapshot_to_load.next_rollback_id);
+
+        // let the app re-attach components that are not part of the snapshot
+        for entity in respawned {
+            for respawn_system in &mut self.respawn_systems {
+                let has_marker = world
+                    .get_entity(entity)
+                    .is_some_and(|entity| entity.contains_type_id(respawn_system.marker));
+                if has_marker {
+                    respawn_system.system.run(entity, world);
+                    respawn_system.system.apply_deferred(world);
+                }
+            }
+        }
+
+        // restore the input history of that frame
+        if let Some(Some(history)) = self.input_histories.get(pos) {
+            world.insert_resource(history.clone());
+        }
+
+        // restore the removals of that frame, mapped to the entities of the loaded snapshot
+        if let Some(saved) = self.removals.get(pos) {
+            let removals = RollbackRemovals::<T>::load(saved, world, &filter);
+            world.insert_resource(removals);
+        }
+
+        // restore the registered states of that frame
+        if let Some(states) = self.states.get(pos) {
+            for (rollback_state, saved) in self.rollback_states.iter().zip(states.iter()) {
+                rollback_state.load(world, saved);
+            }
+        }
+
+        let _ = world.try_run_schedule(&*self.hooks.post_load);
     }
 
     pub(crate) fn advance_frame(
@@ -251,13 +489,178 @@ impl<T: Config> GgrsStage<T> {
         world: &mut World,
     ) {
         debug!("advancing to frame: {}", self.frame + 1);
+        if let Some(mut history) = world.get_resource_mut::<InputHistory<T>>() {
+            history.push(inputs.clone());
+        }
         world.insert_resource(PlayerInputs::<T>(inputs));
-        world.run_schedule(GgrsSchedule);
+        self.set_frame(world, self.frame);
+        self.last_frame_tick = world.read_change_tick();
+        // removals that happened outside of the frame, e.g. by loading a snapshot, are not reported
+        self.skip_removals(world);
+        let filter = RollbackFilter::new(world, self.scope);
+        tick_timers(world, &filter);
+        // transitions queued in the previous frame are applied at the start of the frame
+        for rollback_state in self.rollback_states.iter() {
+            rollback_state.apply_transition(world, self.frame);
+        }
+        world.run_schedule(&*self.schedule);
+        self.collect_removals(world);
+        if cfg!(debug_assertions) {
+            self.warn_unregistered_changes(world, self.last_frame_tick);
+        }
         world.remove_resource::<PlayerInputs<T>>();
         self.frame += 1;
+        self.update_info.frames_advanced += 1;
         debug!("frame {} completed", self.frame);
     }
 
+    /// Updates the confirmed frame and despawns all entities that have been despawned with
+    /// `despawn_rollback()` in a frame that is now confirmed.
+    pub(crate) fn confirm_frame(&mut self, confirmed_frame: i32, world: &mut World) {
+        self.confirmed_frame = self.confirmed_frame.max(confirmed_frame);
+        world.insert_resource(ConfirmedFrameCount::<T>::new(self.confirmed_frame));
+
+        let filter = RollbackFilter::new(world, self.scope);
+        let mut query = world.query::<(Entity, &PendingRollbackDespawn)>();
+        let confirmed_despawns: Vec<Entity> = query
+            .iter(world)
+            .filter(|(entity, pending)| {
+                pending.frame <= self.confirmed_frame
+                    && filter.matches_scope(world.entity(*entity).archetype())
+            })
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in confirmed_despawns {
+            // descendants may have been despawned together with their ancestor already
+            if world.get_entity(entity).is_some() {
+                despawn_with_children_recursive(world, entity);
+            }
+        }
+    }
+
+    /// Publishes the frame that is advanced or loaded to the systems and commands of this instance.
+    fn set_frame(&self, world: &mut World, frame: Frame) {
+        world.insert_resource(RollbackFrameCount::<T>::new(frame));
+        world.insert_resource(RollbackTime::<T>::new(frame, self.update_frequency));
+        world
+            .get_resource_or_insert_with(RollbackFrames::default)
+            .0
+            .insert(self.scope, frame);
+    }
+
+    /// Returns the component ids of all registered components that exist in the world.
+    fn registered_component_ids(&self, world: &World) -> Vec<(ComponentId, TypeId)> {
+        let type_registry = self.type_registry.read();
+        type_registry
+            .iter()
+            .filter(|registration| registration.data::<ReflectComponent>().is_some())
+            .filter_map(|registration| {
+                let type_id = registration.type_id();
+                Some((world.components().get_id(type_id)?, type_id))
+            })
+            .collect()
+    }
+
+    /// Marks all pending removal events of registered components as read.
+    fn skip_removals(&mut self, world: &World) {
+        for (component_id, _) in self.registered_component_ids(world) {
+            if let Some(events) = world.removed_components().get(component_id) {
+                self.removal_readers
+                    .entry(component_id)
+                    .or_default()
+                    .clear(events);
+            }
+        }
+    }
+
+    /// Collects the removals of registered components during the last frame into
+    /// `RollbackRemovals<T>`.
+    fn collect_removals(&mut self, world: &mut World) {
+        let filter = RollbackFilter::new(world, self.scope);
+        let mut removals = RollbackRemovals::<T>::default();
+        for (component_id, type_id) in self.registered_component_ids(world) {
+            if let Some(events) = world.removed_components().get(component_id) {
+                let removed: Vec<Entity> = self
+                    .removal_readers
+                    .entry(component_id)
+                    .or_default()
+                    .iter(events)
+                    .cloned()
+                    .map(Entity::from)
+                    .filter(|&entity| rollback_id(world, entity, &filter).is_some())
+                    .collect();
+                if !removed.is_empty() {
+                    removals.insert(type_id, removed);
+                }
+            }
+        }
+        world.insert_resource(removals);
+    }
+
+    /// Warns about component types that were mutated on `Rollback` entities during the last frame,
+    /// but are not registered for rollback. Such changes are not restored on a rollback and will
+    /// most likely lead to a desync.
+    fn warn_unregistered_changes(&mut self, world: &World, last_run: Tick) {
+        let Some(rollback_id) = world.component_id::<Rollback>() else {
+            return;
+        };
+        // the hierarchy is restored separately, without being registered
+        let hierarchy_ids = [
+            world.component_id::<Parent>(),
+            world.component_id::<Children>(),
+        ];
+        let filter = RollbackFilter::new(world, self.scope);
+        let this_run = world.read_change_tick();
+        let type_registry = self.type_registry.read();
+
+        for archetype in world
+            .archetypes()
+            .iter()
+            .filter(|archetype| filter.matches(archetype))
+        {
+            for component_id in archetype.components() {
+                if component_id == rollback_id
+                    || hierarchy_ids.contains(&Some(component_id))
+                    || self.unregistered_warned.contains(&component_id)
+                {
+                    continue;
+                }
+
+                let Some(info) = world.components().get_info(component_id) else {
+                    continue;
+                };
+                let registered = info
+                    .type_id()
+                    .and_then(|type_id| type_registry.get(type_id))
+                    .is_some_and(|registration| registration.data::<ReflectComponent>().is_some());
+                if registered {
+                    continue;
+                }
+
+                // newly added components (e.g. presentation components of freshly spawned
+                // entities) are fine, we only care about mutations of existing state
+                let mutated = archetype.entities().iter().any(|entity| {
+                    world
+                        .entity(entity.entity())
+                        .get_change_ticks_by_id(component_id)
+                        .is_some_and(|ticks| {
+                            ticks.is_changed(last_run, this_run)
+                                && !ticks.is_added(last_run, this_run)
+                        })
+                });
+                if mutated {
+                    warn!(
+                        "Component {} was changed on a rollback entity in {:?}, but is not registered for rollback. \
+                        Register it with `register_rollback_component()` or it will not be restored on rollbacks.",
+                        info.name(),
+                        self.schedule
+                    );
+                    self.unregistered_warned.insert(component_id);
+                }
+            }
+        }
+    }
+
     pub(crate) fn set_update_frequency(&mut self, update_frequency: usize) {
         self.update_frequency = update_frequency
     }
@@ -265,4 +668,28 @@ impl<T: Config> GgrsStage<T> {
     pub(crate) fn set_type_registry(&mut self, type_registry: TypeRegistry) {
         self.type_registry = type_registry;
     }
+
+    pub(crate) fn set_respawn_systems(&mut self, respawn_systems: Vec<RespawnSystem>) {
+        self.respawn_systems = respawn_systems;
+    }
+
+    pub(crate) fn set_schedule(&mut self, schedule: BoxedScheduleLabel) {
+        self.schedule = schedule;
+    }
+
+    pub(crate) fn set_hooks(&mut self, hooks: HookSchedules) {
+        self.hooks = hooks;
+    }
+
+    pub(crate) fn set_scope(&mut self, scope: Option<ComponentId>) {
+        self.scope = scope;
+    }
+
+    pub(crate) fn set_preserve_entity_ids(&mut self, preserve_entity_ids: bool) {
+        self.preserve_entity_ids = preserve_entity_ids;
+    }
+
+    pub(crate) fn set_rollback_states(&mut self, rollback_states: Vec<RollbackState>) {
+        self.rollback_states = rollback_states;
+    }
 }
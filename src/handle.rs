@@ -0,0 +1,52 @@
+use bevy::{
+    asset::{Asset, HandleUntyped},
+    prelude::*,
+    reflect::FromType,
+};
+
+/// Type data of asset handle components registered with `GgrsPlugin::register_rollback_handle()`.
+///
+/// Reflection does not preserve whether a handle is strong, so restored handles would be weak and
+/// their assets could be unloaded during a rollback. Snapshots keep strong handles to the assets of
+/// all saved handle components instead, and restored handles are made strong again.
+#[derive(Clone)]
+pub(crate) struct ReflectRollbackHandle {
+    keep_alive: fn(&dyn Reflect) -> Option<HandleUntyped>,
+    make_strong: fn(&mut World, Entity),
+}
+
+impl ReflectRollbackHandle {
+    /// Returns a strong handle to the asset of the reflected handle component, if it is strong.
+    pub(crate) fn keep_alive(&self, component: &dyn Reflect) -> Option<HandleUntyped> {
+        (self.keep_alive)(component)
+    }
+
+    /// Makes the handle component of the entity strong, if it is weak.
+    pub(crate) fn make_strong(&self, world: &mut World, entity: Entity) {
+        (self.make_strong)(world, entity)
+    }
+}
+
+impl<A: Asset> FromType<Handle<A>> for ReflectRollbackHandle {
+    fn from_type() -> Self {
+        Self {
+            keep_alive: |component| {
+                component
+                    .downcast_ref::<Handle<A>>()
+                    .filter(|handle| handle.is_strong())
+                    .map(Handle::clone_untyped)
+            },
+            make_strong: |world, entity| {
+                let is_weak = world.get::<Handle<A>>(entity).is_some_and(Handle::is_weak);
+                if !is_weak || !world.contains_resource::<Assets<A>>() {
+                    return;
+                }
+                world.resource_scope(|world, assets: Mut<Assets<A>>| {
+                    let mut handle = world.get_mut::<Handle<A>>(entity).unwrap();
+                    // the handle still points to the same asset, so this is not a change
+                    handle.bypass_change_detection().make_strong(&assets);
+                });
+            },
+        }
+    }
+}
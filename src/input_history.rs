@@ -0,0 +1,74 @@
+use bevy::prelude::Resource;
+use ggrs::{Config, InputStatus, PlayerHandle};
+use std::collections::VecDeque;
+
+/// Holds the inputs of all players for the last few frames, the most recent frame first.
+///
+/// Added by `GgrsPlugin::with_input_history()`. The history is updated before every frame is
+/// advanced, so the most recent entry always equals `PlayerInputs<T>`. It is saved and restored
+/// alongside the world snapshots, so it stays consistent across rollbacks.
+#[derive(Resource)]
+pub struct InputHistory<T: Config> {
+    frames: VecDeque<Vec<(T::Input, InputStatus)>>,
+    capacity: usize,
+}
+
+impl<T: Config> Clone for InputHistory<T> {
+    fn clone(&self) -> Self {
+        Self {
+            frames: self.frames.clone(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+impl<T: Config> InputHistory<T> {
+    /// Creates an empty history holding the inputs of up to `capacity` frames.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// The maximum number of frames kept in the history.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of frames currently in the history.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns true if no frame has been advanced yet.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Returns the inputs of all players `frames_ago` frames before the current one.
+    /// `frame(0)` returns the inputs of the frame currently being advanced.
+    pub fn frame(&self, frames_ago: usize) -> Option<&[(T::Input, InputStatus)]> {
+        self.frames.get(frames_ago).map(|inputs| inputs.as_slice())
+    }
+
+    /// Returns the inputs of a single player, starting with the frame currently being advanced.
+    pub fn player(
+        &self,
+        handle: PlayerHandle,
+    ) -> impl Iterator<Item = &(T::Input, InputStatus)> + '_ {
+        self.frames
+            .iter()
+            .filter_map(move |inputs| inputs.get(handle))
+    }
+
+    pub(crate) fn push(&mut self, inputs: Vec<(T::Input, InputStatus)>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_back();
+        }
+        self.frames.push_front(inputs);
+    }
+}
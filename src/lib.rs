@@ -2,34 +2,184 @@
 #![forbid(unsafe_code)] // let us try
 
 use bevy::{
-    ecs::schedule::{LogLevel, ScheduleBuildSettings, ScheduleLabel},
+    asset::Asset,
+    ecs::{
+        component::ComponentId,
+        schedule::{BoxedScheduleLabel, LogLevel, ScheduleBuildSettings, ScheduleLabel},
+    },
     prelude::*,
-    reflect::{FromType, GetTypeRegistration, TypeRegistry, TypeRegistryInternal},
+    reflect::{
+        FromReflect, FromType, GetTypeRegistration, ReflectFromReflect, TypeRegistry,
+        TypeRegistryInternal,
+    },
+    utils::HashMap,
 };
-use ggrs::{Config, InputStatus, P2PSession, PlayerHandle, SpectatorSession, SyncTestSession};
-use ggrs_stage::GgrsStage;
+use ggrs::{
+    Config, Frame, InputStatus, P2PSession, PlayerHandle, SpectatorSession, SyncTestSession,
+};
+use ggrs_stage::{GgrsStage, InputSystem, RespawnSystem};
+use handle::ReflectRollbackHandle;
 use parking_lot::RwLock;
-use std::sync::Arc;
+use rollback::{RollbackRegistries, RollbackScopes};
+use state::RollbackState;
+use std::{any::TypeId, marker::PhantomData, sync::Arc};
 
 pub use ggrs;
 
-pub use rollback::{AddRollbackCommand, AddRollbackCommandExtension, Rollback};
+pub use fixed::{Fixed, FixedVec3, RollbackTransform, RollbackTransformPlugin};
+pub use input_history::InputHistory;
+pub use pool::{
+    AcquirePooledCommand, PoolActive, PoolCommandsExtension, PoolEntityCommandsExtension, Pooled,
+    ReleasePooledCommand,
+};
+pub use removal::{RollbackRemovals, RollbackRemovedComponents};
+pub use rng::RollbackRng;
+pub use rollback::{
+    AddRollbackCommand, AddRollbackCommandExtension, DespawnRollbackCommand,
+    DespawnRollbackCommandExtension, PendingRollbackDespawn, Rollback, SpawnRollbackCommand,
+    SpawnRollbackCommandExtension,
+};
+pub use time::{RollbackStopwatch, RollbackTime, RollbackTimer};
+pub use world_snapshot::RestoreMode;
 
+pub(crate) mod fixed;
 pub(crate) mod ggrs_stage;
+pub(crate) mod handle;
+pub(crate) mod input_history;
+pub(crate) mod pool;
+pub(crate) mod removal;
+pub(crate) mod rng;
 pub(crate) mod rollback;
+pub(crate) mod state;
+pub(crate) mod time;
 pub(crate) mod world_snapshot;
 
+/// Snapshot internals, only exposed for the benchmarks with the `bench` feature. Not part of the
+/// public API.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod __bench {
+    use bevy::{ecs::component::Tick, prelude::World, reflect::TypeRegistry};
+
+    use crate::{rollback::RollbackFilter, world_snapshot::WorldSnapshot};
+
+    /// A snapshot of the unscoped rollback entities and the registered resources of a world.
+    pub struct Snapshot(WorldSnapshot);
+
+    /// Takes a snapshot like the unscoped plugin instance does before advancing a frame.
+    pub fn save(world: &World, type_registry: &TypeRegistry, last_frame_tick: Tick) -> Snapshot {
+        let filter = RollbackFilter::new(world, None);
+        Snapshot(WorldSnapshot::from_world(
+            world,
+            type_registry,
+            &filter,
+            last_frame_tick,
+        ))
+    }
+
+    /// Loads a snapshot like the unscoped plugin instance does for a rollback.
+    pub fn load(
+        snapshot: &Snapshot,
+        world: &mut World,
+        type_registry: &TypeRegistry,
+        last_frame_tick: Tick,
+    ) {
+        let filter = RollbackFilter::new(world, None);
+        snapshot
+            .0
+            .write_to_world(world, type_registry, &filter, 0, false, last_frame_tick);
+    }
+}
+
 pub mod prelude {
     pub use crate::{
-        AddRollbackCommandExtension, GgrsPlugin, GgrsSchedule, PlayerInputs, Rollback, Session,
+        AddRollbackCommandExtension, ConfirmedFrameCount, DespawnRollbackCommandExtension, Fixed,
+        FixedVec3, GgrsPlugin, GgrsPostUpdate, GgrsSchedule, GgrsSet, GgrsUpdateInfo, InputHistory,
+        LocalInputs, PlayerInputs, PoolActive, PoolCommandsExtension, PoolEntityCommandsExtension,
+        Pooled, PostLoad, PostRollback, PreRollback, PreSave, RestoreMode, Rollback,
+        RollbackFrameCount, RollbackRemovedComponents, RollbackRng, RollbackSet, RollbackStopwatch,
+        RollbackTime, RollbackTimer, RollbackTransform, RollbackTransformPlugin, Session,
+        SpawnRollbackCommandExtension,
     };
 }
 
 const DEFAULT_FPS: usize = 60;
 
+/// The default schedule that is run whenever GGRS advances the game by a single frame.
 #[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
 pub struct GgrsSchedule;
 
+/// System sets of the plugin in `PreUpdate`.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum GgrsSet {
+    /// Collects local inputs, advances the session and runs the rollback schedule for all frames
+    /// of the update. Systems writing `LocalInputs` in `PreUpdate` have to run before this set.
+    Advance,
+}
+
+/// Predefined system sets of the rollback schedule, run in the order they are declared. Systems
+/// don't have to be placed in any of them, but plugins that do can rely on a consistent order.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum RollbackSet {
+    /// Reads the `PlayerInputs` and turns them into intents, e.g. desired velocities.
+    Input,
+    /// Moves entities and resolves collisions.
+    Physics,
+    /// Runs the game logic reacting to the new state.
+    Logic,
+    /// Despawns entities and cleans up state at the end of the frame.
+    Cleanup,
+}
+
+/// Runs before a snapshot is taken. Systems in this schedule may still modify the state that is
+/// saved.
+///
+/// This and the other hook schedules are run by every plugin instance that doesn't replace them,
+/// see `GgrsPlugin::with_pre_save_schedule()` and its siblings.
+#[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct PreSave;
+
+/// Runs before a snapshot is loaded for a rollback, while the world still holds the predicted state.
+#[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct PreRollback;
+
+/// Runs after a snapshot has been loaded, before any frames are simulated again. Entities
+/// respawned by the rollback have already been passed to the respawn systems.
+#[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct PostLoad;
+
+/// Runs once a rollback is complete, after all frames have been simulated again.
+#[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct PostRollback;
+
+/// The hook schedules of a plugin instance.
+pub(crate) struct HookSchedules {
+    pub(crate) pre_save: BoxedScheduleLabel,
+    pub(crate) pre_rollback: BoxedScheduleLabel,
+    pub(crate) post_load: BoxedScheduleLabel,
+    pub(crate) post_rollback: BoxedScheduleLabel,
+}
+
+impl Default for HookSchedules {
+    fn default() -> Self {
+        Self {
+            pre_save: Box::new(PreSave),
+            pre_rollback: Box::new(PreRollback),
+            post_load: Box::new(PostLoad),
+            post_rollback: Box::new(PostRollback),
+        }
+    }
+}
+
+/// Runs once after all plugin instances have advanced the frames of an update, including the
+/// frames simulated again by rollbacks. It only runs if at least one frame was advanced, see
+/// `GgrsUpdateInfo`.
+///
+/// It runs in `PreUpdate`, so systems like cameras and UI can react to the final state of the
+/// update before rendering.
+#[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct GgrsPostUpdate;
+
 /// Defines the Session that the GGRS Plugin should expect as a resource.
 #[derive(Resource)]
 pub enum Session<T: Config> {
@@ -42,34 +192,251 @@ pub enum Session<T: Config> {
 #[derive(Resource, Deref, DerefMut)]
 pub struct PlayerInputs<T: Config>(Vec<(T::Input, InputStatus)>);
 
+/// The frame that is currently being advanced (or has just been loaded) by the rollback stage of
+/// the plugin instance for `T`.
+#[derive(Resource)]
+pub struct RollbackFrameCount<T: Config>(pub(crate) Frame, PhantomData<T>);
+
+impl<T: Config> RollbackFrameCount<T> {
+    pub(crate) fn new(frame: Frame) -> Self {
+        Self(frame, PhantomData)
+    }
+}
+
+impl<T: Config> Default for RollbackFrameCount<T> {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl<T: Config> std::fmt::Debug for RollbackFrameCount<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("RollbackFrameCount").field(&self.0).finish()
+    }
+}
+
+impl<T: Config> Clone for RollbackFrameCount<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Config> Copy for RollbackFrameCount<T> {}
+
+impl<T: Config> PartialEq for RollbackFrameCount<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T: Config> Eq for RollbackFrameCount<T> {}
+
+impl<T: Config> std::ops::Deref for RollbackFrameCount<T> {
+    type Target = Frame;
+
+    fn deref(&self) -> &Frame {
+        &self.0
+    }
+}
+
+/// Describes the frames that the plugin instance for `T` advanced during the last update, e.g. for
+/// systems in the `GgrsPostUpdate` schedule.
+#[derive(Resource)]
+pub struct GgrsUpdateInfo<T: Config> {
+    pub(crate) frames_advanced: u32,
+    pub(crate) rolled_back: bool,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Config> Default for GgrsUpdateInfo<T> {
+    fn default() -> Self {
+        Self {
+            frames_advanced: 0,
+            rolled_back: false,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Config> std::fmt::Debug for GgrsUpdateInfo<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GgrsUpdateInfo")
+            .field("frames_advanced", &self.frames_advanced)
+            .field("rolled_back", &self.rolled_back)
+            .finish()
+    }
+}
+
+impl<T: Config> Clone for GgrsUpdateInfo<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Config> Copy for GgrsUpdateInfo<T> {}
+
+impl<T: Config> PartialEq for GgrsUpdateInfo<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.frames_advanced == other.frames_advanced && self.rolled_back == other.rolled_back
+    }
+}
+
+impl<T: Config> Eq for GgrsUpdateInfo<T> {}
+
+impl<T: Config> GgrsUpdateInfo<T> {
+    /// The number of frames advanced during the update, including frames simulated again by
+    /// rollbacks.
+    pub fn frames_advanced(&self) -> u32 {
+        self.frames_advanced
+    }
+
+    /// Returns true if a snapshot was loaded during the update.
+    pub fn rolled_back(&self) -> bool {
+        self.rolled_back
+    }
+}
+
+/// The most recent frame of the plugin instance for `T` for which all inputs have been confirmed,
+/// so it will never be rolled back. `-1` if no frame has been confirmed yet.
+#[derive(Resource)]
+pub struct ConfirmedFrameCount<T: Config>(pub(crate) Frame, PhantomData<T>);
+
+impl<T: Config> ConfirmedFrameCount<T> {
+    pub(crate) fn new(frame: Frame) -> Self {
+        Self(frame, PhantomData)
+    }
+}
+
+impl<T: Config> Default for ConfirmedFrameCount<T> {
+    fn default() -> Self {
+        Self::new(-1)
+    }
+}
+
+impl<T: Config> std::fmt::Debug for ConfirmedFrameCount<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ConfirmedFrameCount").field(&self.0).finish()
+    }
+}
+
+impl<T: Config> Clone for ConfirmedFrameCount<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Config> Copy for ConfirmedFrameCount<T> {}
+
+impl<T: Config> PartialEq for ConfirmedFrameCount<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T: Config> Eq for ConfirmedFrameCount<T> {}
+
+impl<T: Config> std::ops::Deref for ConfirmedFrameCount<T> {
+    type Target = Frame;
+
+    fn deref(&self) -> &Frame {
+        &self.0
+    }
+}
+
+/// Set when any plugin instance advanced a frame during the current update, so `GgrsPostUpdate`
+/// runs once after all instances.
+#[derive(Resource, Default)]
+pub(crate) struct PostUpdatePending(pub(crate) bool);
+
+/// Runs the `GgrsPostUpdate` schedule if any plugin instance advanced a frame.
+fn run_post_update(world: &mut World) {
+    let mut pending = world.resource_mut::<PostUpdatePending>();
+    if std::mem::take(&mut pending.0) {
+        let _ = world.try_run_schedule(GgrsPostUpdate);
+    }
+}
+
+/// Inputs of the local players, written by regular systems when the plugin is configured with
+/// `GgrsPlugin::with_local_inputs()`.
+///
+/// The rollback stage runs in `PreUpdate` in `GgrsSet::Advance` and reads this resource whenever it
+/// advances a frame, so inputs written during `Update` (or later) are used by the frames advanced in
+/// the next app update. Systems in `PreUpdate` ordered `.before(GgrsSet::Advance)` provide the inputs
+/// of the current update instead. The resource is not cleared by the plugin. Local players without
+/// an entry send zeroed inputs.
+#[derive(Resource, Deref, DerefMut)]
+pub struct LocalInputs<T: Config>(pub HashMap<PlayerHandle, T::Input>);
+
+impl<T: Config> Default for LocalInputs<T> {
+    fn default() -> Self {
+        Self(HashMap::default())
+    }
+}
+
 /// A builder to configure GGRS for a bevy app.
+///
+/// Several plugin instances can be added to the same app in order to run independent rollback
+/// simulations side by side. Every instance needs its own `Config` type (which also keys the
+/// `Session<T>` and `PlayerInputs<T>` resources), its own schedule (see `with_schedule()`)
+/// and its own entities (see `with_scope()`). The hook schedules like `PostLoad` are shared by
+/// default, give instances their own ones with `with_post_load_schedule()` and its siblings.
+///
+/// # Panics
+///
+/// Building the plugin panics if its schedule already exists. Systems have to be added to the
+/// rollback schedule after the plugin, e.g. `app.add_ggrs_plugin(..).add_systems(GgrsSchedule, ..)`,
+/// since adding them first creates the schedule.
 pub struct GgrsPlugin<T: Config + Send + Sync> {
-    input_system: Option<Box<dyn System<In = PlayerHandle, Out = T::Input>>>,
+    input_system: Option<InputSystem<T>>,
+    input_history: Option<usize>,
+    respawn_systems: Vec<RespawnSystem>,
     fps: usize,
     type_registry: TypeRegistry,
+    schedule: BoxedScheduleLabel,
+    hooks: HookSchedules,
+    scope: Option<fn(&mut World) -> ComponentId>,
+    preserve_entity_ids: bool,
+    rollback_states: Vec<RollbackState>,
+    rng_seed: Option<u64>,
 }
 
 impl<T: Config + Send + Sync> Default for GgrsPlugin<T> {
     fn default() -> Self {
         Self {
             input_system: None,
+            input_history: None,
+            respawn_systems: Vec::new(),
             fps: DEFAULT_FPS,
             type_registry: TypeRegistry {
                 internal: Arc::new(RwLock::new({
                     let mut r = TypeRegistryInternal::empty();
-                    // `Parent` and `Children` must be registered so that their `ReflectMapEntities`
-                    // data may be used.
-                    //
-                    // While this is a little bit of a weird spot to register these, are the only
-                    // Bevy core types implementing `MapEntities`, so for now it's probably fine to
-                    // just manually register these here.
+                    // `Parent` and `Children` are not registered here, the hierarchy of rollback
+                    // entities is restored separately through Bevy's hierarchy commands.
                     //
                     // The user can still register any custom types with `register_rollback_type()`.
-                    r.register::<Parent>();
-                    r.register::<Children>();
+                    //
+                    // The active flag of pooled entities is always rolled back, since acquiring
+                    // and releasing pooled entities has to be undone by rollbacks.
+                    r.register::<PoolActive>();
+                    // Per-entity random number streams are always rolled back. The resource is
+                    // registered by the instance that uses it with `with_rng()`, since resources
+                    // are shared by all plugin instances.
+                    r.register::<RollbackRng>();
+                    // Timers are ticked by the plugin, so they are always rolled back as well.
+                    r.register::<RollbackTimer>();
+                    r.register::<RollbackStopwatch>();
+                    // Deterministic transforms are only useful when they are rolled back and
+                    // included in the checksum.
+                    r.register::<RollbackTransform>();
                     r
                 })),
             },
+            schedule: Box::new(GgrsSchedule),
+            hooks: HookSchedules::default(),
+            scope: None,
+            preserve_entity_ids: false,
+            rollback_states: Vec::new(),
+            rng_seed: None,
         }
     }
 }
@@ -91,11 +458,139 @@ impl<T: Config + Send + Sync> GgrsPlugin<T> {
         mut self,
         input_fn: impl IntoSystem<PlayerHandle, T::Input, Params>,
     ) -> Self {
-        self.input_system = Some(Box::new(IntoSystem::into_system(input_fn)));
+        self.input_system = Some(InputSystem::PerPlayer(Box::new(IntoSystem::into_system(
+            input_fn,
+        ))));
+        self
+    }
+
+    /// Registers a system that takes all local player handles and the frame the inputs are collected for,
+    /// and returns the inputs of all those players at once, in the same order as the handles.
+    /// This is an alternative to `with_input_system()`, running the system only once per frame.
+    pub fn with_batch_input_system<Params>(
+        mut self,
+        input_fn: impl IntoSystem<(Vec<PlayerHandle>, Frame), Vec<T::Input>, Params>,
+    ) -> Self {
+        self.input_system = Some(InputSystem::Batch(Box::new(IntoSystem::into_system(
+            input_fn,
+        ))));
+        self
+    }
+
+    /// Changes the schedule that is run to advance the game by a single frame. Defaults to
+    /// `GgrsSchedule`. Each plugin instance in an app needs its own schedule.
+    ///
+    /// # Panics
+    ///
+    /// Building the plugin panics if the schedule already exists, e.g. because another instance
+    /// uses it, or systems were added to it before the plugin.
+    pub fn with_schedule(mut self, label: impl ScheduleLabel) -> Self {
+        self.schedule = Box::new(label);
+        self
+    }
+
+    /// Changes the schedule that is run before a snapshot is taken. Defaults to `PreSave`, which is
+    /// shared by all instances that don't replace it.
+    pub fn with_pre_save_schedule(mut self, label: impl ScheduleLabel) -> Self {
+        self.hooks.pre_save = Box::new(label);
+        self
+    }
+
+    /// Changes the schedule that is run before a snapshot is loaded. Defaults to `PreRollback`,
+    /// which is shared by all instances that don't replace it.
+    pub fn with_pre_rollback_schedule(mut self, label: impl ScheduleLabel) -> Self {
+        self.hooks.pre_rollback = Box::new(label);
+        self
+    }
+
+    /// Changes the schedule that is run after a snapshot has been loaded. Defaults to `PostLoad`,
+    /// which is shared by all instances that don't replace it.
+    pub fn with_post_load_schedule(mut self, label: impl ScheduleLabel) -> Self {
+        self.hooks.post_load = Box::new(label);
+        self
+    }
+
+    /// Changes the schedule that is run once a rollback is complete. Defaults to `PostRollback`,
+    /// which is shared by all instances that don't replace it.
+    pub fn with_post_rollback_schedule(mut self, label: impl ScheduleLabel) -> Self {
+        self.hooks.post_rollback = Box::new(label);
+        self
+    }
+
+    /// Restricts this plugin instance to `Rollback` entities which also have the `Marker`
+    /// component. Entities with the marker are ignored by the unscoped plugin instance.
+    ///
+    /// The marker is registered for rollback as well, so respawned entities keep their scope.
+    pub fn with_scope<Marker>(mut self) -> Self
+    where
+        Marker: GetTypeRegistration + Reflect + Default + Component,
+    {
+        self.scope = Some(World::init_component::<Marker>);
+        self.register_rollback_component::<Marker>()
+    }
+
+    /// Reads local inputs from the `LocalInputs<T>` resource instead of running an input system.
+    /// This is an alternative to `with_input_system()`, allowing inputs to be collected by regular
+    /// systems, for example in `Update` after all input events have been processed.
+    pub fn with_local_inputs(mut self) -> Self {
+        self.input_system = Some(InputSystem::Resource);
+        self
+    }
+
+    /// Inserts a `RollbackRng` resource created from `seed` and registers it for rollback in this
+    /// plugin instance. All peers have to use the same seed.
+    pub fn with_rng(mut self, seed: u64) -> Self {
+        self.rng_seed = Some(seed);
+        self.register_rollback_resource::<RollbackRng>()
+    }
+
+    /// Adds an `InputHistory<T>` resource, holding the inputs of the last `frames` frames.
+    pub fn with_input_history(mut self, frames: usize) -> Self {
+        self.input_history = Some(frames);
+        self
+    }
+
+    /// If enabled, rollback entities that are respawned when loading a snapshot reuse the exact
+    /// entity index and generation they had when the snapshot was taken, so `Entity` references
+    /// stored anywhere in the app stay valid, even in types without `ReflectMapEntities`.
+    ///
+    /// If the index has been taken by another entity in the meantime, a new entity is spawned
+    /// instead and references are only mapped through `ReflectMapEntities`.
+    ///
+    /// Respawning resets the generation of the index to the one in the snapshot. An `Entity` that
+    /// was handed out for the same index before the rollback, e.g. for a short-lived entity that
+    /// reused the index and was despawned again, has a newer generation and may later alias an
+    /// unrelated entity that reuses the index once more. Don't keep such references around.
+    pub fn with_preserved_entity_ids(mut self, preserve: bool) -> Self {
+        self.preserve_entity_ids = preserve;
+        self
+    }
+
+    /// Registers a system that is run for every entity with the `Marker` component that had to be
+    /// spawned from scratch while loading a snapshot. Such entities only have their `Rollback` and
+    /// registered rollback components, so the system can re-attach meshes, materials, sprites and
+    /// other presentation components. The system receives the respawned entity as input.
+    ///
+    /// `Marker` has to be registered for rollback as well, so it is present on respawned entities.
+    pub fn register_respawn_system<Marker, Params>(
+        mut self,
+        respawn_fn: impl IntoSystem<Entity, (), Params>,
+    ) -> Self
+    where
+        Marker: Component,
+    {
+        self.respawn_systems.push(RespawnSystem {
+            marker: TypeId::of::<Marker>(),
+            system: Box::new(IntoSystem::into_system(respawn_fn)),
+        });
         self
     }
 
     /// Registers a type of component for saving and loading during rollbacks.
+    ///
+    /// `Added` and `Changed` filters see the same components after a rollback as in the first
+    /// simulation of a frame, except for components that were removed after the loaded frame.
+    /// Those are inserted again and are seen as added in the next frame.
     pub fn register_rollback_component<Type>(self) -> Self
     where
         Type: GetTypeRegistration + Reflect + Default + Component,
@@ -109,6 +604,42 @@ impl<T: Config + Send + Sync> GgrsPlugin<T> {
         self
     }
 
+    /// Registers a type of component for saving and loading during rollbacks, which is restored
+    /// with the given `RestoreMode` instead of the default `RestoreMode::Replace`.
+    pub fn register_rollback_component_with_mode<Type>(self, mode: RestoreMode) -> Self
+    where
+        Type: GetTypeRegistration + Reflect + FromReflect + Default + Component,
+    {
+        let mut registry = self.type_registry.write();
+        registry.register::<Type>();
+
+        let registration = registry.get_mut(std::any::TypeId::of::<Type>()).unwrap();
+        registration.insert(<ReflectComponent as FromType<Type>>::from_type());
+        registration.insert(<ReflectFromReflect as FromType<Type>>::from_type());
+        registration.insert(mode);
+        drop(registry);
+        self
+    }
+
+    /// Registers asset handles of type `Handle<A>` as components for saving and loading during
+    /// rollbacks. The assets of all saved handles are kept loaded as long as they can be restored
+    /// by a rollback, and restored handles are strong.
+    ///
+    /// Only handles that are components themselves are supported, handles within other components
+    /// are restored as weak handles.
+    pub fn register_rollback_handle<A: Asset>(self) -> Self {
+        let mut registry = self.type_registry.write();
+        registry.register::<Handle<A>>();
+
+        let registration = registry
+            .get_mut(std::any::TypeId::of::<Handle<A>>())
+            .unwrap();
+        registration.insert(<ReflectComponent as FromType<Handle<A>>>::from_type());
+        registration.insert(<ReflectRollbackHandle as FromType<Handle<A>>>::from_type());
+        drop(registry);
+        self
+    }
+
     /// Registers a type of resource for saving and loading during rollbacks.
     pub fn register_rollback_resource<Type>(self) -> Self
     where
@@ -123,11 +654,23 @@ impl<T: Config + Send + Sync> GgrsPlugin<T> {
         self
     }
 
+    /// Registers a state type for saving and loading during rollbacks. Both `State<S>` and
+    /// `NextState<S>` are saved, and are part of the checksum.
+    ///
+    /// Transitions queued with `NextState<S>` are applied at the start of the next frame, running
+    /// the `OnExit`, `OnTransition` and `OnEnter` schedules within the rollback. The `OnEnter`
+    /// schedule of the initial state runs in the first frame. Don't add the state with
+    /// `App::add_state()` as well, or transitions would also be applied outside of the rollback.
+    pub fn register_rollback_state<S: States>(mut self) -> Self {
+        self.rollback_states.push(RollbackState::new::<S>());
+        self
+    }
+
     /// Consumes the builder and makes changes on the bevy app according to the settings.
     pub fn build(self, app: &mut App) {
-        let mut input_system = self
-            .input_system
-            .expect("Adding an input system through GGRSBuilder::with_input_system is required");
+        let mut input_system = self.input_system.expect(
+            "Adding an input system through GGRSBuilder::with_input_system, with_batch_input_system or with_local_inputs is required",
+        );
         // ggrs stage
         input_system.initialize(&mut app.world);
         let mut stage = GgrsStage::<T>::new(input_system);
@@ -138,10 +681,73 @@ impl<T: Config + Send + Sync> GgrsPlugin<T> {
             ambiguity_detection: LogLevel::Error,
             ..default()
         });
-        app.add_schedule(GgrsSchedule, schedule);
+        // already Bevy's default, but snapshots rely on the commands of a frame being applied
+        // before the snapshot of the next frame is taken
+        schedule.set_apply_final_deferred(true);
+        schedule.configure_sets(
+            (
+                RollbackSet::Input,
+                RollbackSet::Physics,
+                RollbackSet::Logic,
+                RollbackSet::Cleanup,
+            )
+                .chain(),
+        );
+        // adding the schedule again would silently replace the systems and sets of another
+        // instance, or systems the app added before the plugin
+        assert!(
+            !app.world.resource::<Schedules>().contains(&*self.schedule),
+            "The schedule {:?} already exists. Every GgrsPlugin instance needs its own schedule (see GgrsPlugin::with_schedule), and systems have to be added to it after the plugin",
+            self.schedule
+        );
+        app.add_schedule(self.schedule.clone(), schedule);
+
+        // remember the scope marker, so unscoped instances can exclude these entities
+        let scope = self.scope.map(|init_marker| init_marker(&mut app.world));
+        if let Some(scope) = scope {
+            app.world
+                .get_resource_or_insert_with(RollbackScopes::default)
+                .0
+                .push(scope);
+        }
+        app.world
+            .get_resource_or_insert_with(RollbackRegistries::default)
+            .0
+            .insert(scope, self.type_registry.clone());
+
+        app.init_resource::<RollbackFrameCount<T>>();
+        app.init_resource::<ConfirmedFrameCount<T>>();
+        app.init_resource::<GgrsUpdateInfo<T>>();
+        app.insert_resource(RollbackTime::<T>::new(0, self.fps));
+        app.init_resource::<RollbackRemovals<T>>();
+        for rollback_state in &self.rollback_states {
+            rollback_state.init(&mut app.world);
+        }
+        if let Some(frames) = self.input_history {
+            app.insert_resource(InputHistory::<T>::new(frames));
+        }
+        if let Some(seed) = self.rng_seed {
+            app.insert_resource(RollbackRng::new(seed));
+        }
+
+        let mut respawn_systems = self.respawn_systems;
+        for respawn_system in &mut respawn_systems {
+            respawn_system.system.initialize(&mut app.world);
+        }
 
         stage.set_type_registry(self.type_registry);
-        app.add_systems(PreUpdate, GgrsStage::<T>::run);
+        stage.set_respawn_systems(respawn_systems);
+        stage.set_schedule(self.schedule);
+        stage.set_hooks(self.hooks);
+        stage.set_scope(scope);
+        stage.set_preserve_entity_ids(self.preserve_entity_ids);
+        stage.set_rollback_states(self.rollback_states);
+        app.add_systems(PreUpdate, GgrsStage::<T>::run.in_set(GgrsSet::Advance));
+        // the post update schedule is shared by all plugin instances, so it is only added once
+        if !app.world.contains_resource::<PostUpdatePending>() {
+            app.init_resource::<PostUpdatePending>();
+            app.add_systems(PreUpdate, run_post_update.after(GgrsSet::Advance));
+        }
         app.insert_resource(stage);
     }
 }
@@ -0,0 +1,117 @@
+use bevy::{
+    ecs::system::{Command, EntityCommand, EntityCommands},
+    log::warn,
+    prelude::*,
+};
+use std::marker::PhantomData;
+
+use crate::{AddRollbackCommandExtension, Rollback};
+
+/// Marks a rollback entity as a member of an entity pool.
+///
+/// Pooled entities are spawned once, before the session starts, and are then acquired and
+/// released inside the `GgrsSchedule` instead of being spawned and despawned. This avoids entity
+/// churn for short-lived entities, such as projectiles, whenever frames are resimulated.
+#[derive(Component, Default, Debug)]
+pub struct Pooled;
+
+/// Marks a pooled entity as currently in use.
+///
+/// This component is saved and restored with the snapshots, so acquiring and releasing entities is
+/// rolled back. Game logic should filter pooled entities with `With<PoolActive>`.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[reflect(Component, Hash)]
+pub struct PoolActive;
+
+/// A `Command` which activates the free pooled entity with the `Marker` component and the lowest
+/// rollback id, and inserts the given bundle into it. Logs a warning if the pool is exhausted.
+pub struct AcquirePooledCommand<Marker, B> {
+    bundle: B,
+    marker: PhantomData<fn() -> Marker>,
+}
+
+impl<Marker: Component, B: Bundle> Command for AcquirePooledCommand<Marker, B> {
+    fn apply(self, world: &mut World) {
+        let mut query = world.query_filtered::<(Entity, &Rollback), (
+            With<Pooled>,
+            With<Marker>,
+            Without<PoolActive>,
+        )>();
+        // the lowest rollback id is picked, so all peers acquire the same entity
+        let free_entity = query
+            .iter(world)
+            .min_by_key(|(_, rollback)| **rollback)
+            .map(|(entity, _)| entity);
+
+        match free_entity {
+            Some(entity) => {
+                world.entity_mut(entity).insert((self.bundle, PoolActive));
+            }
+            None => warn!(
+                "No free pooled entity with {} left, the pool is exhausted",
+                std::any::type_name::<Marker>()
+            ),
+        }
+    }
+}
+
+/// An `EntityCommand` which returns a pooled entity to its pool.
+pub struct ReleasePooledCommand;
+
+impl EntityCommand for ReleasePooledCommand {
+    fn apply(self, id: Entity, world: &mut World) {
+        if let Some(mut entity_mut) = world.get_entity_mut(id) {
+            entity_mut.remove::<PoolActive>();
+        }
+    }
+}
+
+mod private {
+    /// Private seal to ensure `PoolCommandsExtension` cannot be implemented by crate consumers.
+    pub trait PoolCommandsExtensionSeal {}
+
+    /// Private seal to ensure `PoolEntityCommandsExtension` cannot be implemented by crate consumers.
+    pub trait PoolEntityCommandsExtensionSeal {}
+}
+
+/// Extension trait for `Commands` which adds methods to fill and use entity pools.
+pub trait PoolCommandsExtension<'w, 's>: private::PoolCommandsExtensionSeal {
+    /// Spawns an inactive pooled rollback entity. Should be used to fill the pool before the
+    /// session starts, the bundle should contain the marker component that identifies the pool.
+    fn spawn_pooled<'a, B: Bundle>(&'a mut self, bundle: B) -> EntityCommands<'w, 's, 'a>;
+
+    /// Activates a free pooled entity with the `Marker` component and inserts the bundle into it.
+    fn acquire_pooled<Marker: Component, B: Bundle>(&mut self, bundle: B);
+}
+
+impl<'w, 's> private::PoolCommandsExtensionSeal for Commands<'w, 's> {}
+
+impl<'w, 's> PoolCommandsExtension<'w, 's> for Commands<'w, 's> {
+    fn spawn_pooled<'a, B: Bundle>(&'a mut self, bundle: B) -> EntityCommands<'w, 's, 'a> {
+        let mut entity_commands = self.spawn((bundle, Pooled));
+        entity_commands.add_rollback();
+        entity_commands
+    }
+
+    fn acquire_pooled<Marker: Component, B: Bundle>(&mut self, bundle: B) {
+        self.add(AcquirePooledCommand::<Marker, B> {
+            bundle,
+            marker: PhantomData,
+        });
+    }
+}
+
+/// Extension trait for `EntityCommands` which adds the `release_to_pool()` method.
+pub trait PoolEntityCommandsExtension: private::PoolEntityCommandsExtensionSeal {
+    /// Returns this pooled entity to its pool, so it can be acquired again.
+    fn release_to_pool(&mut self) -> &mut Self;
+}
+
+impl<'w, 's, 'a> private::PoolEntityCommandsExtensionSeal for EntityCommands<'w, 's, 'a> {}
+
+impl<'w, 's, 'a> PoolEntityCommandsExtension for EntityCommands<'w, 's, 'a> {
+    fn release_to_pool(&mut self) -> &mut Self {
+        self.add(ReleasePooledCommand);
+        self
+    }
+}
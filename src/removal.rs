@@ -0,0 +1,137 @@
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use ggrs::Config;
+use std::{any::TypeId, marker::PhantomData};
+
+use crate::{
+    rollback::{PendingRollbackDespawn, RollbackFilter},
+    world_snapshot::{pending_despawn_map, rollback_id_map},
+    Rollback,
+};
+
+/// Holds the entities that had a registered rollback component removed during the last frame.
+///
+/// Unlike Bevy's `RemovedComponents<T>`, this does not report the removals caused by loading a
+/// snapshot, and it is saved and restored alongside the world snapshots. This way, every removal
+/// is reported exactly once per simulated frame, during the frame after the one it happened in.
+/// Only rollback entities of the plugin instance are reported, so removals caused by despawning an
+/// entity are not reported unless it is despawned with `despawn_rollback()`.
+/// Read it through the `RollbackRemovedComponents` system parameter.
+#[derive(Resource)]
+pub struct RollbackRemovals<T: Config> {
+    removed: HashMap<TypeId, Vec<Entity>>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Config> Default for RollbackRemovals<T> {
+    fn default() -> Self {
+        Self {
+            removed: HashMap::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Config> Clone for RollbackRemovals<T> {
+    fn clone(&self) -> Self {
+        Self {
+            removed: self.removed.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Config> RollbackRemovals<T> {
+    /// Returns the entities that had a component of the given type removed during the last frame.
+    pub fn removed(&self, type_id: TypeId) -> &[Entity] {
+        self.removed.get(&type_id).map_or(&[], |entities| entities)
+    }
+
+    pub(crate) fn insert(&mut self, type_id: TypeId, entities: Vec<Entity>) {
+        self.removed.insert(type_id, entities);
+    }
+
+    /// Saves the removals by the rollback ids of the entities, see `SavedRemovals`.
+    pub(crate) fn save(&self, world: &World, filter: &RollbackFilter) -> SavedRemovals {
+        let removed = self
+            .removed
+            .iter()
+            .map(|(&type_id, entities)| {
+                let rollback_ids = entities
+                    .iter()
+                    .filter_map(|&entity| rollback_id(world, entity, filter))
+                    .collect();
+                (type_id, rollback_ids)
+            })
+            .collect();
+        SavedRemovals(removed)
+    }
+
+    /// Restores saved removals, after the snapshot of the same frame has been loaded.
+    pub(crate) fn load(saved: &SavedRemovals, world: &mut World, filter: &RollbackFilter) -> Self {
+        let mut entities = rollback_id_map(world, filter);
+        // live entities take precedence over pending despawns with the same rollback id
+        for (rollback, entity) in pending_despawn_map(world, filter) {
+            entities.entry(rollback).or_insert(entity);
+        }
+
+        let removed = saved
+            .0
+            .iter()
+            .map(|(&type_id, rollback_ids)| {
+                let removed = rollback_ids
+                    .iter()
+                    .filter_map(|rollback_id| entities.get(rollback_id).copied())
+                    .collect();
+                (type_id, removed)
+            })
+            .collect();
+        Self {
+            removed,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// The removals of a frame, saved alongside the snapshot of that frame. Entities are stored by
+/// their rollback id, since they may have another entity id once the snapshot is loaded.
+#[derive(Default)]
+pub(crate) struct SavedRemovals(HashMap<TypeId, Vec<Rollback>>);
+
+/// Returns the rollback id of an entity of the plugin instance, which may be pending despawn.
+pub(crate) fn rollback_id(
+    world: &World,
+    entity: Entity,
+    filter: &RollbackFilter,
+) -> Option<Rollback> {
+    let entity = world.get_entity(entity)?;
+    if !filter.matches_scope(world.archetypes().get(entity.location().archetype_id)?) {
+        return None;
+    }
+    entity.get::<Rollback>().copied().or_else(|| {
+        entity
+            .get::<PendingRollbackDespawn>()
+            .map(|pending| pending.rollback)
+    })
+}
+
+/// A system parameter which returns the entities that had their `C` component removed during the
+/// last frame, see `RollbackRemovals`. `C` has to be registered for rollback.
+///
+/// Use this instead of `RemovedComponents<C>` in systems of the rollback schedule.
+#[derive(SystemParam)]
+pub struct RollbackRemovedComponents<'w, T: Config, C: Component> {
+    removals: Res<'w, RollbackRemovals<T>>,
+    marker: PhantomData<C>,
+}
+
+impl<'w, T: Config, C: Component> RollbackRemovedComponents<'w, T, C> {
+    /// Iterates over the entities that had their `C` component removed during the last frame.
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.removals.removed(TypeId::of::<C>()).iter().copied()
+    }
+
+    /// Returns true if no `C` component was removed during the last frame.
+    pub fn is_empty(&self) -> bool {
+        self.removals.removed(TypeId::of::<C>()).is_empty()
+    }
+}
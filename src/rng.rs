@@ -0,0 +1,104 @@
+use bevy::prelude::*;
+use std::ops::Range;
+
+use crate::Rollback;
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Scrambles the bits of `z`, the output function of SplitMix64.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A small deterministic random number generator (SplitMix64) that is saved and restored during
+/// rollbacks, so all peers draw identical sequences as long as they use the same seed.
+///
+/// It is registered for rollback as a component by default. To use it as a resource, add it with
+/// `GgrsPlugin::with_rng()` and a seed all peers agreed on when starting the session.
+/// Entities that need their own sequence, independent of the order in which systems draw numbers,
+/// can get a stream derived from their `Rollback` id with `stream()`.
+#[derive(Resource, Component, Reflect, Default, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[reflect(Component, Hash)]
+pub struct RollbackRng {
+    seed: u64,
+    state: u64,
+}
+
+impl RollbackRng {
+    /// Creates a new generator from a seed.
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: seed }
+    }
+
+    /// The seed this generator was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns an independent generator for a rollback entity, derived from the seed of this
+    /// generator and the rollback id. Insert it on the entity to give it its own stream.
+    pub fn stream(&self, rollback: &Rollback) -> Self {
+        Self::new(mix(
+            self.seed ^ (rollback.id() as u64).wrapping_mul(GOLDEN_GAMMA)
+        ))
+    }
+
+    /// Returns the next random `u64`.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        mix(self.state)
+    }
+
+    /// Returns the next random `u32`.
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Returns a random `bool`.
+    pub fn next_bool(&mut self) -> bool {
+        self.next_u64() >> 63 == 1
+    }
+
+    /// Returns a random `f32` in `[0, 1)`. The conversion is exact, so the result is identical on
+    /// all platforms.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Returns a random `f64` in `[0, 1)`. The conversion is exact, so the result is identical on
+    /// all platforms.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a uniformly distributed random number in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is empty.
+    pub fn range_u32(&mut self, range: Range<u32>) -> u32 {
+        assert!(!range.is_empty(), "cannot sample from an empty range");
+        let len = range.end - range.start;
+        // reject the values that would make some results more likely than others
+        let zone = u32::MAX - (u32::MAX - len + 1) % len;
+        loop {
+            let value = self.next_u32();
+            if value <= zone {
+                return range.start + value % len;
+            }
+        }
+    }
+
+    /// Returns a uniformly distributed random number in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is empty.
+    pub fn range_i32(&mut self, range: Range<i32>) -> i32 {
+        assert!(!range.is_empty(), "cannot sample from an empty range");
+        let len = range.end.abs_diff(range.start);
+        range.start.wrapping_add(self.range_u32(0..len) as i32)
+    }
+}
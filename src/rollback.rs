@@ -1,34 +1,241 @@
 use bevy::{
-    ecs::system::{EntityCommand, EntityCommands},
-    prelude::{Component, Entity, World},
+    ecs::{
+        archetype::Archetype,
+        component::ComponentId,
+        system::{EntityCommand, EntityCommands},
+    },
+    hierarchy::despawn_with_children_recursive,
+    log::warn,
+    prelude::{
+        Bundle, Children, Commands, Component, Entity, ReflectComponent, Resource, Visibility,
+        World,
+    },
+    reflect::TypeRegistry,
+    utils::HashMap,
 };
+use ggrs::Frame;
+
+use crate::world_snapshot::registered_components;
 
 /// This component flags an entity as being included in the rollback save/load schedule with GGRS.
 ///
 /// You must use the `AddRollbackCommand` when spawning an entity to add this component. Alternatively,
 /// you can use the `add_rollback()` extension method provided by `AddRollbackCommandExtension`.
-#[derive(Component, Hash, PartialEq, Eq, Clone, Copy, Debug)]
-pub struct Rollback(Entity);
+///
+/// The contained id is allocated from a counter that is saved and restored with the snapshots, so
+/// it is identical on all peers, as long as rollback entities are spawned in the same order.
+#[derive(Component, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub struct Rollback(u32);
 
 impl Rollback {
-    /// Creates a new `Rollback` component from an `Entity`.
-    pub(crate) fn new(entity: Entity) -> Self {
-        Self(entity)
+    /// Creates a new `Rollback` component from a rollback id.
+    pub(crate) fn new(id: u32) -> Self {
+        Self(id)
+    }
+
+    /// Returns the deterministic rollback id of this entity.
+    pub fn id(&self) -> u32 {
+        self.0
+    }
+}
+
+/// Allocates rollback ids. Every plugin scope has its own counter, which is saved and restored
+/// by the plugin instance of that scope.
+#[derive(Resource, Default)]
+pub(crate) struct RollbackIdProvider {
+    next_ids: HashMap<Option<ComponentId>, u32>,
+}
+
+impl RollbackIdProvider {
+    fn next(&mut self, scope: Option<ComponentId>) -> Rollback {
+        let next_id = self.next_ids.entry(scope).or_default();
+        let rollback = Rollback::new(*next_id);
+        *next_id = next_id
+            .checked_add(1)
+            .expect("Rollback id overflow, you have spawned too many rollback entities");
+        rollback
+    }
+
+    pub(crate) fn next_id(&self, scope: Option<ComponentId>) -> u32 {
+        self.next_ids.get(&scope).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn set_next_id(&mut self, scope: Option<ComponentId>, next_id: u32) {
+        self.next_ids.insert(scope, next_id);
     }
 }
 
 /// An `EntityCommand` which adds a `Rollback` component to an entity.
+///
+/// If the entity belongs to a scoped plugin instance, the scope marker must already be present
+/// when this command is applied.
 pub struct AddRollbackCommand;
 
+/// Returns the scope marker of the plugin instance the entity belongs to.
+fn entity_scope(world: &World, id: Entity) -> Option<ComponentId> {
+    let entity = world.entity(id);
+    world.get_resource::<RollbackScopes>().and_then(|scopes| {
+        scopes
+            .0
+            .iter()
+            .copied()
+            .find(|&scope| entity.contains_id(scope))
+    })
+}
+
 impl EntityCommand for AddRollbackCommand {
     fn apply(self, id: Entity, world: &mut World) {
-        world.entity_mut(id).insert(Rollback::new(id));
+        let scope = entity_scope(world, id);
+        let rollback = world
+            .get_resource_or_insert_with(RollbackIdProvider::default)
+            .next(scope);
+        world.entity_mut(id).insert(rollback);
+    }
+}
+
+/// An `EntityCommand` which inserts a bundle together with a `Rollback` component, so the entity
+/// never exists without being tagged for rollback, see `SpawnRollbackCommandExtension`.
+///
+/// If the entity belongs to a scoped plugin instance, the bundle should contain the scope marker.
+pub struct SpawnRollbackCommand<B: Bundle> {
+    pub bundle: B,
+}
+
+impl<B: Bundle> EntityCommand for SpawnRollbackCommand<B> {
+    fn apply(self, id: Entity, world: &mut World) {
+        world.entity_mut(id).insert(self.bundle);
+        AddRollbackCommand.apply(id, world);
+    }
+}
+
+/// Added by the `DespawnRollbackCommand` to rollback entities that were despawned in a frame which
+/// has not been confirmed by GGRS yet.
+///
+/// Such entities lose their `Rollback` component and all components registered for rollback, so
+/// they no longer take part in the simulation, and are hidden. Only components that are not rolled
+/// back, like meshes and materials, are kept. If a rollback restores a frame from before the despawn, the original entity is restored,
+/// otherwise it is despawned for real as soon as GGRS confirms the frame it was despawned in.
+#[derive(Component, Debug)]
+pub struct PendingRollbackDespawn {
+    pub(crate) rollback: Rollback,
+    pub(crate) frame: Frame,
+    pub(crate) visibility: Option<Visibility>,
+}
+
+impl PendingRollbackDespawn {
+    /// Returns the frame in which the entity was despawned.
+    pub fn frame(&self) -> Frame {
+        self.frame
+    }
+
+    /// Turns the entity back into a regular rollback entity.
+    pub(crate) fn revive(world: &mut World, entity: Entity) {
+        let mut entity_mut = world.entity_mut(entity);
+        if let Some(pending) = entity_mut.take::<PendingRollbackDespawn>() {
+            entity_mut.insert(pending.rollback);
+            if let Some(visibility) = pending.visibility {
+                entity_mut.insert(visibility);
+            }
+        }
+    }
+}
+
+/// An `EntityCommand` which despawns a rollback entity and all of its descendants once the current
+/// frame is confirmed. Until then, the entities are kept around as `PendingRollbackDespawn`.
+///
+/// Entities without a `Rollback` component are despawned immediately.
+pub struct DespawnRollbackCommand;
+
+impl EntityCommand for DespawnRollbackCommand {
+    fn apply(self, id: Entity, world: &mut World) {
+        let Some(entity) = world.get_entity(id) else {
+            warn!("Could not despawn entity {id:?} because it doesn't exist in this World.");
+            return;
+        };
+        if !entity.contains::<Rollback>() {
+            warn!("despawn_rollback() was used on {id:?}, which is not a rollback entity");
+            despawn_with_children_recursive(world, id);
+            return;
+        }
+
+        let scope = entity_scope(world, id);
+        let frame = world
+            .get_resource::<RollbackFrames>()
+            .and_then(|frames| frames.0.get(&scope).copied())
+            .unwrap_or(0);
+
+        // collect the entity and all of its descendants
+        let mut entities = vec![id];
+        let mut i = 0;
+        while i < entities.len() {
+            if let Some(children) = world.get::<Children>(entities[i]) {
+                entities.extend(children.iter().copied());
+            }
+            i += 1;
+        }
+
+        for entity in entities {
+            let Some(&rollback) = world.get_entity(entity).and_then(|e| e.get::<Rollback>()) else {
+                // non-rollback descendants are kept as they are, until they are despawned for real
+                continue;
+            };
+            remove_registered_components(world, entity);
+
+            let mut entity_mut = world.entity_mut(entity);
+            // descendants are hidden through visibility inheritance
+            let visibility = match entity == id {
+                true => entity_mut.get::<Visibility>().copied(),
+                false => None,
+            };
+            entity_mut.remove::<Rollback>();
+            if visibility.is_some() {
+                entity_mut.insert(Visibility::Hidden);
+            }
+            entity_mut.insert(PendingRollbackDespawn {
+                rollback,
+                frame,
+                visibility,
+            });
+        }
+    }
+}
+
+/// Removes the components registered for rollback by the plugin instance of the entity, except for
+/// the scope marker. A revived entity gets them back from the snapshot.
+fn remove_registered_components(world: &mut World, entity: Entity) {
+    let scope = entity_scope(world, entity);
+    let Some(type_registry) = world
+        .get_resource::<RollbackRegistries>()
+        .and_then(|registries| registries.0.get(&scope))
+        .cloned()
+    else {
+        return;
+    };
+    let type_registry = type_registry.read();
+
+    let archetype_id = world.entity(entity).location().archetype_id;
+    let reflect_components: Vec<ReflectComponent> =
+        registered_components(world, &world.archetypes()[archetype_id], &type_registry)
+            .into_iter()
+            .filter(|&(component_id, _, _)| Some(component_id) != scope)
+            .map(|(_, _, reflect_component)| reflect_component.clone())
+            .collect();
+
+    let mut entity_mut = world.entity_mut(entity);
+    for reflect_component in reflect_components {
+        reflect_component.remove(&mut entity_mut);
     }
 }
 
 mod private {
     /// Private seal to ensure `AddRollbackCommandExtension` cannot be implemented by crate consumers.
     pub trait AddRollbackCommandExtensionSeal {}
+
+    /// Private seal to ensure `DespawnRollbackCommandExtension` cannot be implemented by crate consumers.
+    pub trait DespawnRollbackCommandExtensionSeal {}
+
+    /// Private seal to ensure `SpawnRollbackCommandExtension` cannot be implemented by crate consumers.
+    pub trait SpawnRollbackCommandExtensionSeal {}
 }
 
 /// Extension trait for `EntityCommands` which adds the `add_rollback()` method.
@@ -45,3 +252,92 @@ impl<'w, 's, 'a> AddRollbackCommandExtension for EntityCommands<'w, 's, 'a> {
         self
     }
 }
+
+/// Extension trait for `Commands` which adds the `spawn_rollback()` method.
+pub trait SpawnRollbackCommandExtension<'w, 's>:
+    private::SpawnRollbackCommandExtensionSeal
+{
+    /// Spawns an entity with the bundle and an automatically generated `Rollback` component, which
+    /// are inserted by the same command.
+    fn spawn_rollback<'a, B: Bundle>(&'a mut self, bundle: B) -> EntityCommands<'w, 's, 'a>;
+}
+
+impl<'w, 's> private::SpawnRollbackCommandExtensionSeal for Commands<'w, 's> {}
+
+impl<'w, 's> SpawnRollbackCommandExtension<'w, 's> for Commands<'w, 's> {
+    fn spawn_rollback<'a, B: Bundle>(&'a mut self, bundle: B) -> EntityCommands<'w, 's, 'a> {
+        let mut entity_commands = self.spawn_empty();
+        entity_commands.add(SpawnRollbackCommand { bundle });
+        entity_commands
+    }
+}
+
+/// Marker components of all scoped plugin instances, see `GgrsPlugin::with_scope()`.
+#[derive(Resource, Default)]
+pub(crate) struct RollbackScopes(pub(crate) Vec<ComponentId>);
+
+/// The type registries of all plugin instances, keyed by their scope marker, so commands can look
+/// up the components registered for rollback.
+#[derive(Resource, Default)]
+pub(crate) struct RollbackRegistries(pub(crate) HashMap<Option<ComponentId>, TypeRegistry>);
+
+/// The current frame of all plugin instances, keyed by their scope marker, so commands know the
+/// frame of the instance an entity belongs to.
+#[derive(Resource, Default)]
+pub(crate) struct RollbackFrames(pub(crate) HashMap<Option<ComponentId>, Frame>);
+
+/// Decides which `Rollback` entities belong to a single plugin instance.
+///
+/// Scoped instances only consider entities carrying their scope marker, while the unscoped
+/// instance considers all entities without any scope marker.
+pub(crate) struct RollbackFilter {
+    rollback: Option<ComponentId>,
+    scope: Option<ComponentId>,
+    excluded: Vec<ComponentId>,
+}
+
+impl RollbackFilter {
+    pub(crate) fn new(world: &World, scope: Option<ComponentId>) -> Self {
+        let excluded = match (scope, world.get_resource::<RollbackScopes>()) {
+            (None, Some(scopes)) => scopes.0.clone(),
+            _ => Vec::new(),
+        };
+        Self {
+            rollback: world.component_id::<Rollback>(),
+            scope,
+            excluded,
+        }
+    }
+
+    /// Returns true if the entities in this archetype belong to the plugin instance.
+    pub(crate) fn matches(&self, archetype: &Archetype) -> bool {
+        match self.rollback {
+            Some(rollback) => archetype.contains(rollback) && self.matches_scope(archetype),
+            None => false,
+        }
+    }
+
+    /// Returns true if the entities in this archetype are within the scope of the plugin instance,
+    /// regardless of whether they are rollback entities.
+    pub(crate) fn matches_scope(&self, archetype: &Archetype) -> bool {
+        match self.scope {
+            Some(scope) => archetype.contains(scope),
+            None => !self.excluded.iter().any(|&id| archetype.contains(id)),
+        }
+    }
+}
+
+/// Extension trait for `EntityCommands` which adds the `despawn_rollback()` method.
+pub trait DespawnRollbackCommandExtension: private::DespawnRollbackCommandExtensionSeal {
+    /// Despawns this rollback entity and its descendants once the current frame is confirmed.
+    /// See `PendingRollbackDespawn` for details.
+    fn despawn_rollback(&mut self);
+}
+
+impl<'w, 's, 'a> private::DespawnRollbackCommandExtensionSeal for EntityCommands<'w, 's, 'a> {}
+
+impl<'w, 's, 'a> DespawnRollbackCommandExtension for EntityCommands<'w, 's, 'a> {
+    fn despawn_rollback(&mut self) {
+        self.add(DespawnRollbackCommand);
+    }
+}
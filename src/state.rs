@@ -0,0 +1,99 @@
+use bevy::{
+    ecs::schedule::{apply_state_transition, run_enter_schedule},
+    prelude::*,
+    reflect::utility::reflect_hasher,
+};
+use ggrs::Frame;
+use std::{
+    any::Any,
+    hash::{Hash, Hasher},
+};
+
+/// The saved `State<S>` and `NextState<S>` of a single state type.
+pub(crate) struct SavedState {
+    value: Box<dyn Any + Send + Sync>,
+    /// The hash of the saved value, which is added to the checksum of the snapshot
+    pub(crate) hash: u64,
+}
+
+/// Saves, loads and transitions a state type registered with
+/// `GgrsPlugin::register_rollback_state()`. States are not reflected, so this keeps the functions
+/// of the concrete type instead.
+#[derive(Clone, Copy)]
+pub(crate) struct RollbackState {
+    init: fn(&mut World),
+    save: fn(&World) -> SavedState,
+    load: fn(&mut World, &SavedState),
+    apply_transition: fn(&mut World, Frame),
+}
+
+impl RollbackState {
+    pub(crate) fn new<S: States>() -> Self {
+        Self {
+            init: init_state::<S>,
+            save: save_state::<S>,
+            load: load_state::<S>,
+            apply_transition: apply_transition::<S>,
+        }
+    }
+
+    /// Inserts the `State<S>` and `NextState<S>` resources, if they don't exist yet.
+    pub(crate) fn init(&self, world: &mut World) {
+        (self.init)(world)
+    }
+
+    pub(crate) fn save(&self, world: &World) -> SavedState {
+        (self.save)(world)
+    }
+
+    /// Restores the saved state and pending transition, without running any transition schedules.
+    /// Their effects on rollback entities and resources are part of the snapshot already.
+    pub(crate) fn load(&self, world: &mut World, saved: &SavedState) {
+        (self.load)(world, saved)
+    }
+
+    /// Applies the transition queued in the previous frame, running the `OnExit`, `OnTransition`
+    /// and `OnEnter` schedules. The `OnEnter` schedule of the initial state runs in the first frame.
+    pub(crate) fn apply_transition(&self, world: &mut World, frame: Frame) {
+        (self.apply_transition)(world, frame)
+    }
+}
+
+fn init_state<S: States>(world: &mut World) {
+    world.init_resource::<State<S>>();
+    world.init_resource::<NextState<S>>();
+}
+
+fn save_state<S: States>(world: &World) -> SavedState {
+    let state = world.resource::<State<S>>().get().clone();
+    let next = world.resource::<NextState<S>>().0.clone();
+
+    let mut hasher = reflect_hasher();
+    state.hash(&mut hasher);
+    next.hash(&mut hasher);
+
+    SavedState {
+        value: Box::new((state, next)),
+        hash: hasher.finish(),
+    }
+}
+
+fn load_state<S: States>(world: &mut World, saved: &SavedState) {
+    let (state, next) = saved.value.downcast_ref::<(S, Option<S>)>().unwrap();
+
+    // only overwrite what differs, so change detection is not triggered needlessly
+    if *world.resource::<State<S>>() != *state {
+        world.insert_resource(State::new(state.clone()));
+    }
+    let mut next_state = world.resource_mut::<NextState<S>>();
+    if next_state.0 != *next {
+        next_state.0 = next.clone();
+    }
+}
+
+fn apply_transition<S: States>(world: &mut World, frame: Frame) {
+    if frame == 0 {
+        run_enter_schedule::<S>(world);
+    }
+    apply_state_transition::<S>(world);
+}
@@ -0,0 +1,289 @@
+use bevy::prelude::*;
+use ggrs::{Config, Frame};
+use instant::Duration;
+use std::marker::PhantomData;
+
+use crate::rollback::RollbackFilter;
+
+/// The deterministic time of the frame that is currently being advanced (or has just been loaded)
+/// by the rollback stage of the plugin instance for `T`. Use this instead of `Time` in the rollback
+/// schedule.
+///
+/// The delta is fixed by `GgrsPlugin::with_update_frequency()`, and the elapsed time is derived
+/// from the frame, so it is identical on all peers and after every rollback.
+#[derive(Resource)]
+pub struct RollbackTime<T: Config> {
+    frame: Frame,
+    delta: Duration,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Config> std::fmt::Debug for RollbackTime<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RollbackTime")
+            .field("frame", &self.frame)
+            .field("delta", &self.delta)
+            .finish()
+    }
+}
+
+impl<T: Config> Clone for RollbackTime<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Config> Copy for RollbackTime<T> {}
+
+impl<T: Config> PartialEq for RollbackTime<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.frame == other.frame && self.delta == other.delta
+    }
+}
+
+impl<T: Config> Eq for RollbackTime<T> {}
+
+impl<T: Config> RollbackTime<T> {
+    pub(crate) fn new(frame: Frame, update_frequency: usize) -> Self {
+        Self {
+            frame,
+            delta: Duration::from_secs_f64(1. / update_frequency as f64),
+            _marker: PhantomData,
+        }
+    }
+
+    /// The frame that is currently being advanced.
+    pub fn frame(&self) -> Frame {
+        self.frame
+    }
+
+    /// The fixed duration of a single frame.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    /// The fixed duration of a single frame in seconds.
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// The time elapsed since the start of the session, including the current frame.
+    pub fn elapsed(&self) -> Duration {
+        self.delta * (self.frame.max(0) as u32 + 1)
+    }
+
+    /// The time elapsed since the start of the session in seconds, including the current frame.
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.elapsed().as_secs_f32()
+    }
+}
+
+/// A timer counting frames instead of wall-clock time, so it finishes in exactly the same frame on
+/// all peers and after every rollback.
+///
+/// Timers on rollback entities are ticked by one frame at the start of every frame, before the
+/// rollback schedule runs. The component is registered for rollback by default.
+#[derive(Component, Reflect, Default, Clone, Debug, Hash, PartialEq, Eq)]
+#[reflect(Component, Hash)]
+pub struct RollbackTimer {
+    duration: u32,
+    elapsed: u32,
+    mode: TimerMode,
+    paused: bool,
+    finished: bool,
+    just_finished: bool,
+}
+
+impl RollbackTimer {
+    /// Creates a new timer finishing after the given number of frames.
+    pub fn new(frames: u32, mode: TimerMode) -> Self {
+        Self {
+            duration: frames,
+            mode,
+            ..default()
+        }
+    }
+
+    /// Creates a new timer finishing after the given duration, rounded up to whole frames.
+    pub fn from_duration<T: Config>(
+        duration: Duration,
+        time: &RollbackTime<T>,
+        mode: TimerMode,
+    ) -> Self {
+        let frames = duration.as_nanos().div_ceil(time.delta().as_nanos());
+        Self::new(frames.try_into().unwrap_or(u32::MAX), mode)
+    }
+
+    /// Returns true if the timer has finished. Repeating timers are only finished in the frame
+    /// they wrapped around.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns true if the timer finished in the current frame.
+    pub fn just_finished(&self) -> bool {
+        self.just_finished
+    }
+
+    /// The number of frames the timer has been running.
+    pub fn elapsed(&self) -> u32 {
+        self.elapsed
+    }
+
+    /// The number of frames after which the timer finishes.
+    pub fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    /// The number of frames until the timer finishes.
+    pub fn remaining(&self) -> u32 {
+        self.duration - self.elapsed
+    }
+
+    /// The ratio of elapsed frames to the duration, between `0.0` and `1.0`.
+    pub fn percent(&self) -> f32 {
+        match self.duration {
+            0 => 1.,
+            duration => self.elapsed as f32 / duration as f32,
+        }
+    }
+
+    /// Returns the mode of the timer.
+    pub fn mode(&self) -> TimerMode {
+        self.mode
+    }
+
+    /// Returns true if the timer is paused.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses the timer, so it is no longer ticked.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Resumes a paused timer.
+    pub fn unpause(&mut self) {
+        self.paused = false;
+    }
+
+    /// Resets the timer, keeping its duration, mode and paused state.
+    pub fn reset(&mut self) {
+        self.elapsed = 0;
+        self.finished = false;
+        self.just_finished = false;
+    }
+
+    /// Advances the timer by a single frame. Called by the plugin at the start of every frame.
+    pub fn tick(&mut self) {
+        if self.paused {
+            self.just_finished = false;
+            // a repeating timer only stays finished in the frame it wrapped around
+            if self.mode == TimerMode::Repeating {
+                self.finished = false;
+            }
+            return;
+        }
+
+        if self.finished && self.mode == TimerMode::Once {
+            self.just_finished = false;
+            return;
+        }
+
+        self.elapsed = (self.elapsed + 1).min(self.duration);
+        self.finished = self.elapsed >= self.duration;
+        self.just_finished = self.finished;
+        if self.finished && self.mode == TimerMode::Repeating {
+            self.elapsed = 0;
+        }
+    }
+}
+
+/// A stopwatch counting frames instead of wall-clock time, so it is identical on all peers and
+/// after every rollback.
+///
+/// Stopwatches on rollback entities are ticked by one frame at the start of every frame, before the
+/// rollback schedule runs. The component is registered for rollback by default.
+#[derive(Component, Reflect, Default, Clone, Debug, Hash, PartialEq, Eq)]
+#[reflect(Component, Hash)]
+pub struct RollbackStopwatch {
+    elapsed: u32,
+    paused: bool,
+}
+
+impl RollbackStopwatch {
+    /// Creates a new stopwatch starting at zero.
+    pub fn new() -> Self {
+        default()
+    }
+
+    /// The number of frames the stopwatch has been running.
+    pub fn elapsed(&self) -> u32 {
+        self.elapsed
+    }
+
+    /// The time the stopwatch has been running.
+    pub fn elapsed_time<T: Config>(&self, time: &RollbackTime<T>) -> Duration {
+        time.delta() * self.elapsed
+    }
+
+    /// Returns true if the stopwatch is paused.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses the stopwatch, so it is no longer ticked.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Resumes a paused stopwatch.
+    pub fn unpause(&mut self) {
+        self.paused = false;
+    }
+
+    /// Resets the elapsed frames to zero.
+    pub fn reset(&mut self) {
+        self.elapsed = 0;
+    }
+
+    /// Advances the stopwatch by a single frame. Called by the plugin at the start of every frame.
+    pub fn tick(&mut self) {
+        if !self.paused {
+            self.elapsed = self.elapsed.saturating_add(1);
+        }
+    }
+}
+
+/// Ticks the timers and stopwatches of all rollback entities of a plugin instance. Called at the
+/// start of every frame, before the rollback schedule runs.
+pub(crate) fn tick_timers(world: &mut World, filter: &RollbackFilter) {
+    let timer_id = world.component_id::<RollbackTimer>();
+    let stopwatch_id = world.component_id::<RollbackStopwatch>();
+
+    let mut timers = Vec::new();
+    let mut stopwatches = Vec::new();
+    for archetype in world
+        .archetypes()
+        .iter()
+        .filter(|archetype| filter.matches(archetype))
+    {
+        let entities = archetype.entities().iter().map(|entity| entity.entity());
+        if timer_id.is_some_and(|id| archetype.contains(id)) {
+            timers.extend(entities.clone());
+        }
+        if stopwatch_id.is_some_and(|id| archetype.contains(id)) {
+            stopwatches.extend(entities);
+        }
+    }
+
+    // like a system would, so the ticked components count as changed in this frame
+    world.increment_change_tick();
+    for entity in timers {
+        world.get_mut::<RollbackTimer>(entity).unwrap().tick();
+    }
+    for entity in stopwatches {
+        world.get_mut::<RollbackStopwatch>(entity).unwrap().tick();
+    }
+}
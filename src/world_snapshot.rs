@@ -1,36 +1,186 @@
 use bevy::{
-    ecs::{entity::EntityMap, reflect::ReflectMapEntities},
+    asset::HandleUntyped,
+    ecs::{
+        archetype::{Archetype, ArchetypeId},
+        component::{ComponentId, Tick},
+        entity::EntityMap,
+        reflect::ReflectMapEntities,
+    },
+    hierarchy::despawn_with_children_recursive,
     prelude::*,
-    reflect::{Reflect, TypeRegistry},
+    reflect::{
+        utility::reflect_hasher, Reflect, ReflectFromReflect, TypeRegistry, TypeRegistryInternal,
+    },
     utils::HashMap,
 };
-use std::{fmt::Debug, num::Wrapping};
+use ggrs::Frame;
+use std::{
+    any::TypeId,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    num::Wrapping,
+};
+
+use crate::{
+    handle::ReflectRollbackHandle,
+    rollback::{PendingRollbackDespawn, Rollback, RollbackFilter},
+};
 
-use crate::rollback::Rollback;
+/// Decides how a registered component is written back to an entity that still has that component
+/// when a snapshot is loaded, see `GgrsPlugin::register_rollback_component_with_mode()`.
+///
+/// Entities without the component always get the saved value inserted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RestoreMode {
+    /// Replaces the component with the saved value, converted through `FromWorld` and
+    /// `Reflect::apply()`. Lists and maps are replaced as a whole.
+    #[default]
+    Replace,
+    /// Applies the saved value in place with `Reflect::apply()`. Note that lists and maps are
+    /// patched instead of replaced, so this is only suitable for components without collections.
+    Apply,
+    /// Overwrites the component in place with the saved value, converted through `FromReflect`.
+    Set,
+}
+
+impl RestoreMode {
+    /// Writes the saved `component` to an entity that already has a component of that type.
+    fn restore(
+        self,
+        entity_mut: &mut EntityMut,
+        reflect_component: &ReflectComponent,
+        reflect_from_reflect: Option<&ReflectFromReflect>,
+        component: &dyn Reflect,
+    ) {
+        // skip components that did not change, so they are not flagged as changed or added
+        let unchanged = reflect_component
+            .reflect_mut(entity_mut)
+            .is_some_and(|current| current.reflect_partial_eq(component) == Some(true));
+        if unchanged {
+            return;
+        }
+
+        match self {
+            RestoreMode::Replace => {
+                // inserting a component the entity already has replaces it in place
+                reflect_component.insert(entity_mut, component);
+            }
+            RestoreMode::Apply => {
+                let mut current = reflect_component.reflect_mut(entity_mut).unwrap();
+                current.apply(component);
+            }
+            RestoreMode::Set => {
+                let value = reflect_from_reflect
+                    .and_then(|from_reflect| from_reflect.from_reflect(component))
+                    .expect("RestoreMode::Set requires the component to implement FromReflect");
+                let mut current = reflect_component.reflect_mut(entity_mut).unwrap();
+                current
+                    .set(value)
+                    .expect("the saved component has a different type than the restored one");
+            }
+        }
+    }
+}
 
 /// Maps rollback_ids to entity id+generation. Necessary to track entities over time.
-fn rollback_id_map(world: &mut World) -> HashMap<Rollback, Entity> {
+pub(crate) fn rollback_id_map(world: &World, filter: &RollbackFilter) -> HashMap<Rollback, Entity> {
     let mut rid_map = HashMap::default();
-    let mut query = world.query::<(Entity, &Rollback)>();
-    for (entity, rollback) in query.iter(world) {
-        assert!(!rid_map.contains_key(rollback));
-        rid_map.insert(*rollback, entity);
+    for archetype in world
+        .archetypes()
+        .iter()
+        .filter(|archetype| filter.matches(archetype))
+    {
+        for archetype_entity in archetype.entities() {
+            let entity = archetype_entity.entity();
+            let rollback = world.get::<Rollback>(entity).unwrap();
+            assert!(!rid_map.contains_key(rollback));
+            rid_map.insert(*rollback, entity);
+        }
     }
     rid_map
 }
 
+/// Maps rollback_ids to entities that are waiting for their despawn to be confirmed.
+pub(crate) fn pending_despawn_map(
+    world: &mut World,
+    filter: &RollbackFilter,
+) -> HashMap<Rollback, Entity> {
+    let mut query = world.query::<(Entity, &PendingRollbackDespawn)>();
+    query
+        .iter(world)
+        .filter(|(entity, _)| filter.matches_scope(world.entity(*entity).archetype()))
+        .map(|(entity, pending)| (pending.rollback, entity))
+        .collect()
+}
+
+/// Returns the components of the archetype that are registered for rollback.
+pub(crate) fn registered_components<'a>(
+    world: &World,
+    archetype: &Archetype,
+    type_registry: &'a TypeRegistryInternal,
+) -> Vec<(ComponentId, TypeId, &'a ReflectComponent)> {
+    archetype
+        .components()
+        .filter_map(|component_id| {
+            let info = world.components().get_info(component_id)?;
+            let registration = type_registry.get(info.type_id()?)?;
+            let reflect_component = registration.data::<ReflectComponent>()?;
+            Some((component_id, registration.type_id(), reflect_component))
+        })
+        .collect()
+}
+
+/// A registered component of a rollback entity, saved in a snapshot.
+struct SavedComponent {
+    pub type_id: TypeId,
+    pub value: Box<dyn Reflect>,
+    /// Whether the component was added in the frame before the snapshot was taken
+    pub added: bool,
+    /// Whether the component was changed in the frame before the snapshot was taken
+    pub changed: bool,
+}
+
 struct RollbackEntity {
     pub entity: Entity,
     pub rollback_id: Rollback,
-    pub components: Vec<Box<dyn Reflect>>,
+    /// The registered components of the entity
+    pub components: Vec<SavedComponent>,
+    /// The parent of the entity when the snapshot was taken
+    pub parent: Option<Entity>,
+    /// The children of the entity when the snapshot was taken, in order
+    pub children: Vec<Entity>,
+}
+
+impl RollbackEntity {
+    /// Spawns a new entity for a rollback entity that no longer exists in the world.
+    ///
+    /// If `preserve_entity_id` is set, the entity is spawned with the exact id it had when the
+    /// snapshot was taken, unless that index has been reused by another entity in the meantime.
+    /// `get_or_spawn()` resets the generation of a free index, see
+    /// `GgrsPlugin::with_preserved_entity_ids()`.
+    fn respawn(&self, world: &mut World, preserve_entity_id: bool) -> Entity {
+        if preserve_entity_id && world.get_entity(self.entity).is_none() {
+            if let Some(mut entity_mut) = world.get_or_spawn(self.entity) {
+                entity_mut.insert(self.rollback_id);
+                return entity_mut.id();
+            }
+            debug!(
+                "could not preserve id of entity {:?}, the index is used by another entity",
+                self.entity
+            );
+        }
+        world.spawn(self.rollback_id).id()
+    }
 }
 
 impl Default for RollbackEntity {
     fn default() -> Self {
         Self {
             entity: Entity::from_raw(0),
-            rollback_id: Rollback::new(Entity::from_raw(0)),
+            rollback_id: Rollback::new(0),
             components: Default::default(),
+            parent: None,
+            children: Vec::new(),
         }
     }
 }
@@ -45,84 +195,147 @@ impl Debug for RollbackEntity {
     }
 }
 
+/// Combines the hash of a component with the rollback id of its entity, so the checksum changes
+/// when values are swapped between entities.
+fn entity_hash(rollback_id: Rollback, hash: u64) -> u64 {
+    let mut hasher = reflect_hasher();
+    rollback_id.hash(&mut hasher);
+    hash.hash(&mut hasher);
+    hasher.finish()
+}
+
 /// Holds registered components of `Rollback` tagged entities, as well as registered resources to save and load from/to the real bevy world.
 /// The `checksum` is the sum of hash-values from all hashable objects. It is a sum for the checksum to be order insensitive. This of course
 /// is not the best checksum to ever exist, but it is a starting point.
+///
+/// Only components and resources that reflect `Hash` are part of the checksum. Floats can't be
+/// hashed, use `RollbackTransform` and `Fixed` for values that should be checked for desyncs.
 #[derive(Default)]
 pub(crate) struct WorldSnapshot {
     entities: Vec<RollbackEntity>,
-    pub resources: Vec<Box<dyn Reflect>>,
+    /// The registered resources, keyed by their type id
+    pub resources: Vec<(TypeId, Box<dyn Reflect>)>,
+    /// The next rollback id to be allocated within the scope of the snapshot
+    pub next_rollback_id: u32,
+    /// Strong handles to the assets of all saved handle components, so they stay loaded as long as
+    /// the snapshot can be restored
+    handles: Vec<HandleUntyped>,
     pub checksum: u64,
 }
 
 impl WorldSnapshot {
-    pub(crate) fn from_world(world: &World, type_registry: &TypeRegistry) -> Self {
+    /// Takes a snapshot of the world. `last_frame_tick` is the change tick at the start of the
+    /// frame before the snapshot, which is used to save which components were added or changed in
+    /// that frame.
+    pub(crate) fn from_world(
+        world: &World,
+        type_registry: &TypeRegistry,
+        filter: &RollbackFilter,
+        last_frame_tick: Tick,
+    ) -> Self {
         let mut snapshot = WorldSnapshot::default();
         let type_registry = type_registry.read();
+        let this_run = world.read_change_tick();
 
-        // create a `RollbackEntity` for every entity tagged with rollback
-        for archetype in world.archetypes().iter() {
-            let entities_offset = snapshot.entities.len();
-            for entity in archetype.entities() {
-                let entity = entity.entity();
-                if let Some(rollback) = world.get::<Rollback>(entity) {
-                    snapshot.entities.push(RollbackEntity {
-                        entity,
-                        rollback_id: *rollback,
-                        components: Vec::new(),
-                    });
-                }
-            }
+        // create a `RollbackEntity` for every entity tagged with rollback, skipping archetypes
+        // without rollback entities and entities that belong to other plugin instances
+        for archetype in world
+            .archetypes()
+            .iter()
+            .filter(|archetype| filter.matches(archetype))
+        {
+            // look up the registered components of the archetype once, instead of once per entity
+            let reflect_components = registered_components(world, archetype, &type_registry);
 
-            // fill the component vectors of rollback entities
-            for component_id in archetype.components() {
-                let reflect_component = world
-                    .components()
-                    .get_info(component_id)
-                    .and_then(|info| type_registry.get(info.type_id().unwrap()))
-                    .and_then(|registration| registration.data::<ReflectComponent>());
-                if let Some(reflect_component) = reflect_component {
-                    for (i, entity) in archetype
-                        .entities()
-                        .iter()
-                        .filter(|&entity| world.get::<Rollback>(entity.entity()).is_some())
-                        .enumerate()
+            let entity_refs: Vec<EntityRef> = archetype
+                .entities()
+                .iter()
+                .map(|archetype_entity| world.entity(archetype_entity.entity()))
+                .collect();
+            let first = snapshot.entities.len();
+            snapshot
+                .entities
+                .extend(entity_refs.iter().map(|entity_ref| {
+                    RollbackEntity {
+                        entity: entity_ref.id(),
+                        rollback_id: *entity_ref.get::<Rollback>().unwrap(),
+                        components: Vec::with_capacity(reflect_components.len()),
+                        parent: entity_ref.get::<Parent>().map(Parent::get),
+                        children: entity_ref
+                            .get::<Children>()
+                            .map(|children| children.to_vec())
+                            .unwrap_or_default(),
+                    }
+                }));
+            let rollback_entities = &mut snapshot.entities[first..];
+
+            // save the archetype column by column. The ticks are read from the storage of the
+            // component, while the values go through reflection, since reading them from the
+            // column directly requires unsafe code.
+            let table = &world.storages().tables[archetype.table_id()];
+            for (component_id, type_id, reflect_component) in reflect_components {
+                let rollback_handle = type_registry.get_type_data::<ReflectRollbackHandle>(type_id);
+                let column = table.get_column(component_id);
+                let sparse_set = world.storages().sparse_sets.get(component_id);
+
+                for ((archetype_entity, entity_ref), rollback_entity) in archetype
+                    .entities()
+                    .iter()
+                    .zip(entity_refs.iter())
+                    .zip(rollback_entities.iter_mut())
+                {
+                    let ticks = match column {
+                        Some(column) => column.get_ticks(archetype_entity.table_row()),
+                        None => sparse_set
+                            .and_then(|sparse_set| sparse_set.get_ticks(archetype_entity.entity())),
+                    };
+                    let (Some(ticks), Some(component)) =
+                        (ticks, reflect_component.reflect(*entity_ref))
+                    else {
+                        continue;
+                    };
+
+                    if let Some(handle) =
+                        rollback_handle.and_then(|handle| handle.keep_alive(component))
                     {
-                        let entity = entity.entity();
-                        let entity_ref = world.entity(entity);
-                        if let Some(component) = reflect_component.reflect(entity_ref) {
-                            assert_eq!(entity, snapshot.entities[entities_offset + i].entity);
-                            // add the hash value of that component to the shapshot checksum, if that component supports hashing
-                            if let Some(hash) = component.reflect_hash() {
-                                // wrapping semantics to avoid overflow
-                                snapshot.checksum =
-                                    (Wrapping(snapshot.checksum) + Wrapping(hash)).0;
-                            }
-                            // add the component to the shapshot
-                            snapshot.entities[entities_offset + i]
-                                .components
-                                .push(component.clone_value());
-                        }
+                        snapshot.handles.push(handle);
+                    }
+                    // add the hash value of that component to the shapshot checksum, if that component supports hashing
+                    if let Some(hash) = component.reflect_hash() {
+                        let hash = entity_hash(rollback_entity.rollback_id, hash);
+                        // wrapping semantics to avoid overflow
+                        snapshot.checksum = (Wrapping(snapshot.checksum) + Wrapping(hash)).0;
                     }
+                    // add the component to the shapshot
+                    rollback_entity.components.push(SavedComponent {
+                        type_id,
+                        value: component.clone_value(),
+                        added: ticks.is_added(last_frame_tick, this_run),
+                        changed: ticks.is_changed(last_frame_tick, this_run),
+                    });
                 }
             }
         }
 
         // go through all resources and clone those that are registered
         for (component_id, _) in world.storages().resources.iter() {
-            let reflect_component = world
+            let registration = world
                 .components()
                 .get_info(component_id)
-                .and_then(|info| type_registry.get(info.type_id().unwrap()))
-                .and_then(|registration| registration.data::<ReflectResource>());
-            if let Some(reflect_resource) = reflect_component {
+                .and_then(|info| type_registry.get(info.type_id()?));
+            let Some(registration) = registration else {
+                continue;
+            };
+            if let Some(reflect_resource) = registration.data::<ReflectResource>() {
                 if let Some(resource) = reflect_resource.reflect(world) {
                     // add the hash value of that resource to the shapshot checksum, if that resource supports hashing
                     if let Some(hash) = resource.reflect_hash() {
                         snapshot.checksum = (Wrapping(snapshot.checksum) + Wrapping(hash)).0;
                     }
                     // add the resource to the shapshot
-                    snapshot.resources.push(resource.clone_value());
+                    snapshot
+                        .resources
+                        .push((registration.type_id(), resource.clone_value()));
                 }
             }
         }
@@ -130,79 +343,154 @@ impl WorldSnapshot {
         snapshot
     }
 
-    pub(crate) fn write_to_world(&self, world: &mut World, type_registry: &TypeRegistry) {
+    /// Restores the snapshot of `frame` and returns all entities that had to be spawned from
+    /// scratch.
+    ///
+    /// Restored components that were not changed in the frame before the snapshot are marked as
+    /// last changed at `last_frame_tick`, the change tick at the start of the last frame, so the
+    /// systems of the rollback schedule don't see them as changed. Components that have to be
+    /// inserted again, because they were removed after the snapshot was taken, are always seen as
+    /// added in the next frame.
+    pub(crate) fn write_to_world(
+        &self,
+        world: &mut World,
+        type_registry: &TypeRegistry,
+        filter: &RollbackFilter,
+        frame: Frame,
+        preserve_entity_ids: bool,
+        last_frame_tick: Tick,
+    ) -> Vec<Entity> {
         let type_registry = type_registry.read();
-        let mut rid_map = rollback_id_map(world);
+        let mut rid_map = rollback_id_map(world, filter);
+        let mut pending_map = pending_despawn_map(world, filter);
+
+        // entities that had to be spawned from scratch
+        let mut respawned = Vec::new();
 
         // Mapping of the old entity ids ( when snapshot was taken ) to new entity ids
         let mut entity_map = EntityMap::default();
 
+        // registered components of the archetypes of restored entities, looked up once per archetype
+        let mut archetype_components = HashMap::<ArchetypeId, Vec<_>>::default();
+
         // first, we write all entities
         for rollback_entity in self.entities.iter() {
             // find the corresponding current entity or create new entity, if it doesn't exist
-            let entity = *rid_map
-                .entry(rollback_entity.rollback_id)
-                .or_insert_with(|| world.spawn(rollback_entity.rollback_id).id());
+            // afterwards, the pair is removed from the map (leftover entities will need to be despawned)
+            let entity = match rid_map.remove(&rollback_entity.rollback_id) {
+                Some(entity) => entity,
+                // if the entity has been despawned after the snapshot was taken, restore it
+                None => match pending_map.remove(&rollback_entity.rollback_id) {
+                    Some(entity) => {
+                        PendingRollbackDespawn::revive(world, entity);
+                        entity
+                    }
+                    None => {
+                        let entity = rollback_entity.respawn(world, preserve_entity_ids);
+                        respawned.push(entity);
+                        entity
+                    }
+                },
+            };
 
             // Add the mapping from the old entity ID to the new entity ID
             entity_map.insert(rollback_entity.entity, entity);
 
-            // for each registered type, check what we need to do
-            for registration in type_registry.iter() {
-                let type_id = registration.type_id();
+            // remove registered components that the entity did not have when the snapshot was taken
+            let archetype_id = world.entity(entity).location().archetype_id;
+            let current_components =
+                archetype_components.entry(archetype_id).or_insert_with(|| {
+                    registered_components(world, &world.archetypes()[archetype_id], &type_registry)
+                });
+            for (_, type_id, reflect_component) in current_components.iter() {
+                if !rollback_entity
+                    .components
+                    .iter()
+                    .any(|saved| saved.type_id == *type_id)
+                {
+                    reflect_component.remove(&mut world.entity_mut(entity));
+                }
+            }
+
+            // then write all components saved in the snapshot
+            for saved in rollback_entity.components.iter() {
+                let Some(registration) = type_registry.get(saved.type_id) else {
+                    continue;
+                };
                 let Some(reflect_component) = registration.data::<ReflectComponent>() else {
                     continue;
                 };
 
-                if world.entity(entity).contains_type_id(type_id) {
-                    // the entity in the world has such a component
-                    match rollback_entity
-                        .components
-                        .iter()
-                        .find(|comp| comp.type_name() == registration.type_name())
-                    {
-                        // if we have data saved in the snapshot, overwrite the world
-                        Some(component) => {
-                            // Note: It's important that we remove and re-insert instead of just
-                            // apply().
-                            //
-                            // For example, an apply() will do an in-place update such that apply an
-                            // array to an array will add items to the array instead of completely
-                            // replacing the current array with the new one.
-                            let mut entity_mut = world.entity_mut(entity);
-                            reflect_component.remove(&mut entity_mut);
-                            reflect_component.insert(&mut entity_mut, &**component);
-                        }
-                        // if we don't have any data saved, we need to remove that component from the entity
-                        None => {
-                            let mut entity_mut = world.entity_mut(entity);
-                            reflect_component.remove(&mut entity_mut);
-                        }
+                let mut entity_mut = world.entity_mut(entity);
+                let present = entity_mut.contains_type_id(saved.type_id);
+                if saved.added {
+                    // components that were added in the frame before the snapshot are re-inserted,
+                    // so they are flagged as added again
+                    if present {
+                        reflect_component.remove(&mut entity_mut);
                     }
+                    reflect_component.insert(&mut entity_mut, &*saved.value);
                 } else {
-                    // the entity in the world has no such component
-                    if let Some(component) = rollback_entity
-                        .components
-                        .iter()
-                        .find(|comp| comp.type_name() == registration.type_name())
-                    {
-                        // if we have data saved in the snapshot, add the component to the entity
-                        let mut entity_mut = world.entity_mut(entity);
-                        reflect_component.insert(&mut entity_mut, &**component);
+                    if present {
+                        let restore_mode = registration
+                            .data::<RestoreMode>()
+                            .copied()
+                            .unwrap_or_default();
+                        restore_mode.restore(
+                            &mut entity_mut,
+                            reflect_component,
+                            registration.data::<ReflectFromReflect>(),
+                            &*saved.value,
+                        );
+                    } else {
+                        // Bevy has no safe API to set the added tick, so components that were
+                        // removed after the snapshot was taken are flagged as added again
+                        reflect_component.insert(&mut entity_mut, &*saved.value);
+                    }
+
+                    // restore whether the component was changed in the frame before the snapshot
+                    let mut entity_mut = world.entity_mut(entity);
+                    let mut current = reflect_component.reflect_mut(&mut entity_mut).unwrap();
+                    if saved.changed {
+                        current.set_changed();
+                    } else {
+                        current.set_last_changed(last_frame_tick);
                     }
-                    // if both the snapshot and the world does not have the registered component, we don't need to to anything
                 }
-            }
 
-            // afterwards, remove the pair from the map (leftover entities will need to be despawned)
-            rid_map.remove(&rollback_entity.rollback_id);
+                // handles written through reflection are weak
+                if let Some(rollback_handle) = registration.data::<ReflectRollbackHandle>() {
+                    rollback_handle.make_strong(world, entity);
+                }
+            }
         }
 
+        // restore the hierarchy before despawning leftovers, so that rollback entities which were
+        // reparented to a leftover entity are not despawned along with it
+        self.restore_parents(world, &entity_map);
+
         // despawn entities which have a rollback component but where not present in the snapshot
         for (_, v) in rid_map.iter() {
-            world.despawn(*v);
+            // the entity may already be gone if it was a descendant of another leftover entity
+            if world.get_entity(*v).is_some() {
+                despawn_with_children_recursive(world, *v);
+            }
         }
 
+        // entities despawned in or after the loaded frame that were not revived were spawned after
+        // the snapshot was taken, so they will never be revived and keep rollback ids that may be
+        // handed out again
+        for (_, entity) in pending_map.iter() {
+            let discarded = world
+                .get::<PendingRollbackDespawn>(*entity)
+                .is_some_and(|pending| pending.frame >= frame);
+            if discarded {
+                despawn_with_children_recursive(world, *entity);
+            }
+        }
+
+        self.restore_children_order(world, &entity_map);
+
         // then, we write all resources
         for registration in type_registry.iter() {
             let reflect_resource = match registration.data::<ReflectResource>() {
@@ -219,10 +507,10 @@ impl WorldSnapshot {
                     match self
                         .resources
                         .iter()
-                        .find(|res| res.type_name() == registration.type_name())
+                        .find(|(type_id, _)| *type_id == registration.type_id())
                     {
                         // if both the world and the snapshot has the resource, apply the values
-                        Some(snapshot_res) => {
+                        Some((_, snapshot_res)) => {
                             reflect_resource.apply(world, &**snapshot_res);
                         }
                         // if only the world has the resource, but it doesn't exist in the snapshot, remove the resource
@@ -232,10 +520,10 @@ impl WorldSnapshot {
                 // the world does not have this resource
                 None => {
                     // if we have saved that resource, add it
-                    if let Some(snapshot_res) = self
+                    if let Some((_, snapshot_res)) = self
                         .resources
                         .iter()
-                        .find(|res| res.type_name() == registration.type_name())
+                        .find(|(type_id, _)| *type_id == registration.type_id())
                     {
                         reflect_resource.insert(world, &**snapshot_res);
                     }
@@ -251,5 +539,63 @@ impl WorldSnapshot {
                 map_entities_reflect.map_all_entities(world, &mut entity_map)
             }
         }
+
+        respawned
+    }
+
+    /// Restores the parent of every entity in the snapshot.
+    ///
+    /// This goes through the hierarchy commands instead of writing `Parent` and `Children` directly,
+    /// so that the `Children` of parents stay consistent and `HierarchyEvent`s are sent.
+    fn restore_parents(&self, world: &mut World, entity_map: &EntityMap) {
+        for rollback_entity in self.entities.iter() {
+            let entity = entity_map.get(rollback_entity.entity).unwrap();
+            // parents outside of the snapshot keep their id, parents that no longer exist are dropped
+            let parent = rollback_entity
+                .parent
+                .map(|parent| entity_map.get(parent).unwrap_or(parent))
+                .filter(|&parent| world.get_entity(parent).is_some());
+
+            if world.get::<Parent>(entity).map(Parent::get) == parent {
+                continue;
+            }
+
+            let mut entity_mut = world.entity_mut(entity);
+            match parent {
+                Some(parent) => entity_mut.set_parent(parent),
+                None => entity_mut.remove_parent(),
+            };
+        }
+    }
+
+    /// Restores the order of the `Children` of every entity in the snapshot. Children that were
+    /// added after the snapshot was taken are moved to the end.
+    fn restore_children_order(&self, world: &mut World, entity_map: &EntityMap) {
+        for rollback_entity in self.entities.iter() {
+            let entity = entity_map.get(rollback_entity.entity).unwrap();
+            let Some(mut children) = world.get_mut::<Children>(entity) else {
+                continue;
+            };
+
+            let order: Vec<Entity> = rollback_entity
+                .children
+                .iter()
+                .map(|&child| entity_map.get(child).unwrap_or(child))
+                .collect();
+            let position = |child: &Entity| {
+                order
+                    .iter()
+                    .position(|other| other == child)
+                    .unwrap_or(usize::MAX)
+            };
+
+            // only sort if necessary, so `Children` is not flagged as changed on every rollback
+            if children
+                .windows(2)
+                .any(|pair| position(&pair[0]) > position(&pair[1]))
+            {
+                children.sort_by_key(position);
+            }
+        }
     }
 }
@@ -0,0 +1,102 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GgrsConfig;
+impl Config for GgrsConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Component, Default)]
+#[reflect(Component)]
+struct Value(i32);
+
+/// The number of `Value` components matching `Added` and `Changed` in every frame, not rolled back.
+#[derive(Resource, Default)]
+struct Detected(HashMap<i32, (usize, usize)>);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+/// Records the detected changes, and makes sure that resimulated frames detect the same changes as
+/// the first simulation of the frame.
+fn detect_system(
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    added: Query<(), Added<Value>>,
+    changed: Query<(), Changed<Value>>,
+    mut detected: ResMut<Detected>,
+) {
+    let counts = (added.iter().count(), changed.iter().count());
+    let first = *detected.0.entry(**frame).or_insert(counts);
+    assert_eq!(
+        first, counts,
+        "different changes detected in frame {}",
+        **frame
+    );
+}
+
+/// Runs after `detect_system`, so its changes are detected in the next frame.
+fn change_system(
+    mut commands: Commands,
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    mut values: Query<&mut Value>,
+) {
+    match **frame {
+        2 => {
+            commands.spawn(Value(0)).add_rollback();
+        }
+        frame if frame % 3 == 0 => {
+            for mut value in values.iter_mut() {
+                value.0 += 1;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Makes sure that rolled back frames see the same `Added` and `Changed` components as when they
+/// were first simulated, also for components restored while loading a snapshot.
+#[test]
+fn change_detection_survives_rollbacks() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<Detected>()
+        .insert_resource(Session::SyncTest(
+            SessionBuilder::<GgrsConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .add_ggrs_plugin(
+            GgrsPlugin::<GgrsConfig>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .register_rollback_component::<Value>(),
+        )
+        .add_systems(GgrsSchedule, (detect_system, change_system).chain());
+
+    while app.world.resource::<Detected>().0.len() < 12 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let detected = &app.world.resource::<Detected>().0;
+    // spawned in frame 2
+    assert_eq!(detected[&3], (1, 1));
+    // changed in frame 3
+    assert_eq!(detected[&4], (0, 1));
+    assert_eq!(detected[&5], (0, 0));
+    // changed in frames 6 and 9
+    assert_eq!(detected[&7], (0, 1));
+    assert_eq!(detected[&8], (0, 0));
+    assert_eq!(detected[&10], (0, 1));
+    assert_eq!(detected[&11], (0, 0));
+}
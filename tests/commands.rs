@@ -0,0 +1,76 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GgrsConfig;
+impl Config for GgrsConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// Holds the frame in which the entity was spawned.
+#[derive(Reflect, Component, Default)]
+struct Spawned(i32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn spawn_system(mut commands: Commands, frame: Res<RollbackFrameCount<GgrsConfig>>) {
+    if **frame % 2 == 0 {
+        commands.spawn_rollback(Spawned(**frame));
+    }
+}
+
+fn assert_commands_applied(untagged: Query<Entity, (With<Spawned>, Without<Rollback>)>) {
+    assert!(
+        untagged.is_empty(),
+        "entities spawned in the frame are not tagged before the snapshot is taken"
+    );
+}
+
+/// Makes sure that the commands of a frame are applied before the snapshot of that frame is
+/// taken, so entities spawned with `spawn_rollback()` are saved, restored and never duplicated.
+#[test]
+fn commands_applied_before_save() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTest(
+            SessionBuilder::<GgrsConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .add_ggrs_plugin(
+            GgrsPlugin::<GgrsConfig>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .register_rollback_component::<Spawned>(),
+        )
+        .add_systems(GgrsSchedule, spawn_system)
+        .add_systems(PreSave, assert_commands_applied);
+
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let last_frame = **app.world.resource::<RollbackFrameCount<GgrsConfig>>();
+    assert!(last_frame > 2, "not enough frames were advanced");
+
+    let mut spawned: Vec<i32> = app
+        .world
+        .query_filtered::<&Spawned, With<Rollback>>()
+        .iter(&app.world)
+        .map(|spawned| spawned.0)
+        .collect();
+    spawned.sort();
+    let expected: Vec<i32> = (0..=last_frame).filter(|frame| frame % 2 == 0).collect();
+    assert_eq!(spawned, expected);
+}
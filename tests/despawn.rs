@@ -0,0 +1,142 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GgrsConfig;
+impl Config for GgrsConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Component, Default)]
+struct Health(i32);
+
+#[derive(Event)]
+struct TriggerEvent;
+
+/// Whether the entity has been despawned in any frame, not rolled back.
+#[derive(Resource, Default)]
+struct Despawned(bool);
+
+/// The entities present after loading a snapshot once the entity has been despawned.
+#[derive(Resource, Default)]
+struct Revived(Vec<Entity>);
+
+fn input_system(_: In<PlayerHandle>, mut events: EventReader<TriggerEvent>) -> u8 {
+    u8::from(events.iter().count() > 0)
+}
+
+fn spawn_world(mut commands: Commands) {
+    commands.spawn(Health(100)).add_rollback();
+}
+
+fn despawn_system(
+    mut commands: Commands,
+    inputs: Res<PlayerInputs<GgrsConfig>>,
+    query: Query<Entity, (With<Health>, With<Rollback>)>,
+    mut despawned: ResMut<Despawned>,
+) {
+    if inputs[0].0 == 1 {
+        for entity in query.iter() {
+            commands.entity(entity).despawn_rollback();
+            despawned.0 = true;
+        }
+    }
+}
+
+/// Deliberately not filtered by `Rollback`, so it would still see pending despawns.
+fn damage_system(mut query: Query<&mut Health>) {
+    for mut health in query.iter_mut() {
+        health.0 -= 1;
+    }
+}
+
+fn record_revived(
+    despawned: Res<Despawned>,
+    query: Query<Entity, (With<Health>, With<Rollback>)>,
+    mut revived: ResMut<Revived>,
+) {
+    if despawned.0 {
+        revived.0.extend(query.iter());
+    }
+}
+
+fn run_frames(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+
+        let mut pending_with_health = app
+            .world
+            .query_filtered::<Entity, (With<PendingRollbackDespawn>, With<Health>)>();
+        assert_eq!(
+            pending_with_health.iter(&app.world).count(),
+            0,
+            "pending despawns still have rollback components"
+        );
+    }
+}
+
+/// Makes sure that despawned rollback entities no longer take part in the simulation, are revived
+/// with their components by rollbacks to earlier frames, and are gone once the despawn is confirmed.
+#[test]
+fn despawn_revive_confirm() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_event::<TriggerEvent>()
+        .init_resource::<Despawned>()
+        .init_resource::<Revived>()
+        .add_systems(Startup, spawn_world)
+        .insert_resource(Session::SyncTest(
+            SessionBuilder::<GgrsConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .add_ggrs_plugin(
+            GgrsPlugin::<GgrsConfig>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .register_rollback_component::<Health>(),
+        )
+        .add_systems(GgrsSchedule, (despawn_system, damage_system).chain())
+        .add_systems(PostLoad, record_revived);
+
+    run_frames(&mut app, 3);
+
+    let original = app
+        .world
+        .query_filtered::<Entity, With<Health>>()
+        .single(&app.world);
+
+    app.world
+        .resource_mut::<Events<TriggerEvent>>()
+        .send(TriggerEvent);
+
+    run_frames(&mut app, 5);
+
+    assert!(
+        app.world.resource::<Despawned>().0,
+        "entity was not despawned"
+    );
+    assert!(
+        app.world.resource::<Revived>().0.contains(&original),
+        "the original entity was not revived by a rollback"
+    );
+
+    // the default prediction window is 8 frames, after which the despawn is confirmed
+    run_frames(&mut app, 20);
+
+    assert!(
+        app.world.get_entity(original).is_none(),
+        "entity was not despawned after the frame was confirmed"
+    );
+    let mut health = app.world.query::<&Health>();
+    assert_eq!(health.iter(&app.world).count(), 0);
+}
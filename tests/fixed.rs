@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+
+fn fixed(value: f32) -> Fixed {
+    Fixed::from_f32(value)
+}
+
+/// Makes sure that the basic operations give exact results where the inputs are representable.
+#[test]
+fn arithmetic_is_exact() {
+    assert_eq!(fixed(1.5) + fixed(2.25), fixed(3.75));
+    assert_eq!(fixed(1.5) - fixed(2.25), fixed(-0.75));
+    assert_eq!(fixed(1.5) * fixed(-2.5), fixed(-3.75));
+    assert_eq!(fixed(-3.75) / fixed(1.5), fixed(-2.5));
+    assert_eq!(fixed(7.0) / fixed(2.0), fixed(3.5));
+    assert_eq!(-fixed(0.5), fixed(-0.5));
+    assert_eq!(Fixed::from_ratio(3, 4), fixed(0.75));
+    assert_eq!(Fixed::from_ratio(-1, 3), -Fixed::from_ratio(1, 3));
+    assert_eq!(Fixed::from_int(3) * Fixed::ONE, Fixed::from_int(3));
+
+    let mut value = Fixed::from_int(2);
+    value += Fixed::ONE;
+    value *= Fixed::from_int(4);
+    value -= Fixed::from_int(2);
+    value /= Fixed::from_int(5);
+    assert_eq!(value, Fixed::from_int(2));
+}
+
+/// Makes sure that overflow wraps around instead of panicking in debug builds.
+#[test]
+fn overflow_wraps() {
+    let epsilon = Fixed::from_bits(1);
+    assert_eq!(Fixed::MAX + epsilon, Fixed::MIN);
+    assert_eq!(Fixed::MIN - epsilon, Fixed::MAX);
+    assert_eq!(-Fixed::MIN, Fixed::MIN);
+    assert_eq!(Fixed::MIN.abs(), Fixed::MIN);
+    assert_eq!(
+        Fixed::MAX * Fixed::from_int(2),
+        Fixed::from_bits(i32::MAX.wrapping_mul(2))
+    );
+    assert_eq!(
+        Fixed::MAX / fixed(0.5),
+        Fixed::from_bits(i32::MAX.wrapping_mul(2))
+    );
+    assert_eq!(
+        Fixed::from_ratio(i32::MAX, 1),
+        Fixed::from_bits(i32::MAX << Fixed::FRAC_BITS)
+    );
+}
+
+/// Makes sure that `floor()` rounds towards negative infinity.
+#[test]
+fn floor_rounds_down() {
+    assert_eq!(fixed(2.5).floor(), 2);
+    assert_eq!(fixed(2.0).floor(), 2);
+    assert_eq!(fixed(-2.0).floor(), -2);
+    assert_eq!(fixed(-2.5).floor(), -3);
+    assert_eq!(Fixed::from_bits(-1).floor(), -1);
+    assert_eq!(Fixed::MIN.floor(), i16::MIN as i32);
+}
+
+/// Makes sure that `sqrt()` is exact for squares, rounds down otherwise and returns zero for
+/// negative numbers.
+#[test]
+fn sqrt_rounds_down() {
+    assert_eq!(Fixed::ZERO.sqrt(), Fixed::ZERO);
+    assert_eq!(Fixed::ONE.sqrt(), Fixed::ONE);
+    assert_eq!(Fixed::from_int(16).sqrt(), Fixed::from_int(4));
+    assert_eq!(fixed(0.25).sqrt(), fixed(0.5));
+    assert_eq!(fixed(-4.0).sqrt(), Fixed::ZERO);
+
+    for value in [
+        Fixed::from_int(2),
+        fixed(0.1),
+        Fixed::from_int(1000),
+        Fixed::MAX,
+    ] {
+        let root = value.sqrt();
+        let next = root + Fixed::from_bits(1);
+        assert!(root * root <= value, "sqrt({value:?}) is too large");
+        assert!(
+            next.to_bits() as i64 * next.to_bits() as i64
+                > (value.to_bits() as i64) << Fixed::FRAC_BITS,
+            "sqrt({value:?}) is too small"
+        );
+    }
+
+    let vector = FixedVec3::new(Fixed::from_int(2), Fixed::from_int(3), Fixed::from_int(6));
+    assert_eq!(vector.length(), Fixed::from_int(7));
+}
+
+/// Makes sure that fixed-point numbers and rollback transforms are hashed, so they are part of the
+/// snapshot checksums.
+#[test]
+fn values_are_hashed() {
+    let hash = |value: &dyn Reflect| value.reflect_hash().expect("value is not hashed");
+    assert_eq!(hash(&fixed(1.5)), hash(&fixed(1.5)));
+    assert_ne!(hash(&fixed(1.5)), hash(&fixed(-1.5)));
+
+    let vector = FixedVec3::new(Fixed::ONE, Fixed::ZERO, fixed(-2.5));
+    let transform = RollbackTransform::from_translation(vector);
+    let moved = RollbackTransform::from_translation(
+        vector + FixedVec3::new(Fixed::ZERO, Fixed::from_bits(1), Fixed::ZERO),
+    );
+    assert_eq!(hash(&vector), hash(&vector));
+    assert_eq!(
+        hash(&transform),
+        hash(&RollbackTransform::from_translation(vector))
+    );
+    assert_ne!(hash(&transform), hash(&moved));
+}
@@ -0,0 +1,207 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GgrsConfig;
+impl Config for GgrsConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Component, Default)]
+struct OldParent;
+
+#[derive(Reflect, Component, Default)]
+struct NewParent;
+
+#[derive(Reflect, Component, Default)]
+struct Root;
+
+#[derive(Reflect, Component, Default)]
+struct Leaf;
+
+#[derive(Event)]
+struct TriggerEvent;
+
+fn input_system(_: In<PlayerHandle>, mut events: EventReader<TriggerEvent>) -> u8 {
+    u8::from(events.iter().count() > 0)
+}
+
+fn build_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(TransformPlugin)
+        .add_event::<TriggerEvent>()
+        .insert_resource(Session::SyncTest(
+            SessionBuilder::<GgrsConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+    app
+}
+
+/// Runs the app for a number of frames, so that some rollbacks happen, and checks the hierarchy
+/// after every update.
+fn run_frames(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+        assert_hierarchy_consistent(&mut app.world);
+    }
+}
+
+/// Every `Parent` must be listed in the `Children` of the parent, and every live entity in
+/// `Children` must point back to its parent.
+fn assert_hierarchy_consistent(world: &mut World) {
+    let mut parents = world.query::<(Entity, &Parent)>();
+    for (entity, parent) in parents.iter(world) {
+        let children = world
+            .get::<Children>(parent.get())
+            .unwrap_or_else(|| panic!("parent of {entity:?} has no children"));
+        assert!(
+            children.contains(&entity),
+            "{entity:?} is missing from the children of its parent"
+        );
+    }
+
+    let mut children = world.query::<(Entity, &Children)>();
+    for (entity, children) in children.iter(world) {
+        for &child in children.iter() {
+            if let Some(parent) = world.get_entity(child).and_then(|c| c.get::<Parent>()) {
+                assert_eq!(parent.get(), entity, "{child:?} has a different parent");
+            }
+        }
+    }
+}
+
+fn spawn_reparent_world(mut commands: Commands) {
+    commands
+        .spawn(OldParent)
+        .add_rollback()
+        .with_children(|parent| {
+            parent.spawn(Leaf).add_rollback();
+        });
+    commands.spawn(NewParent).add_rollback();
+}
+
+fn reparent_system(
+    mut commands: Commands,
+    inputs: Res<PlayerInputs<GgrsConfig>>,
+    leaf: Query<Entity, With<Leaf>>,
+    new_parent: Query<Entity, With<NewParent>>,
+) {
+    if inputs[0].0 == 1 {
+        commands
+            .entity(leaf.single())
+            .set_parent(new_parent.single());
+    }
+}
+
+/// Makes sure that moving a rollback entity to another parent is undone and redone consistently.
+#[test]
+fn reparenting() {
+    let mut app = build_app();
+    app.add_systems(Startup, spawn_reparent_world)
+        .add_ggrs_plugin(
+            GgrsPlugin::<GgrsConfig>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .register_rollback_component::<OldParent>()
+                .register_rollback_component::<NewParent>()
+                .register_rollback_component::<Leaf>(),
+        )
+        .add_systems(GgrsSchedule, reparent_system);
+
+    run_frames(&mut app, 2);
+
+    app.world
+        .resource_mut::<Events<TriggerEvent>>()
+        .send(TriggerEvent);
+
+    run_frames(&mut app, 5);
+
+    let leaf = app
+        .world
+        .query_filtered::<Entity, With<Leaf>>()
+        .single(&app.world);
+    let new_parent = app
+        .world
+        .query_filtered::<Entity, With<NewParent>>()
+        .single(&app.world);
+    let old_parent = app
+        .world
+        .query_filtered::<Entity, With<OldParent>>()
+        .single(&app.world);
+
+    assert_eq!(app.world.get::<Parent>(leaf).unwrap().get(), new_parent);
+    assert_eq!(&**app.world.get::<Children>(new_parent).unwrap(), &[leaf]);
+    assert!(
+        app.world.get::<Children>(old_parent).is_none(),
+        "old parent still has children"
+    );
+}
+
+fn spawn_nested_world(mut commands: Commands) {
+    commands.spawn(Root).add_rollback().with_children(|root| {
+        root.spawn_empty().add_rollback().with_children(|middle| {
+            middle.spawn(Leaf).add_rollback();
+            middle.spawn(Leaf).add_rollback();
+        });
+    });
+}
+
+fn despawn_root_system(
+    mut commands: Commands,
+    inputs: Res<PlayerInputs<GgrsConfig>>,
+    root: Query<Entity, With<Root>>,
+) {
+    if inputs[0].0 == 1 {
+        if let Ok(root) = root.get_single() {
+            commands.entity(root).despawn_recursive();
+        }
+    }
+}
+
+/// Makes sure that recursively despawned hierarchies are restored with all their relationships
+/// during rollbacks, and are gone once the despawn has been resimulated.
+#[test]
+fn nested_despawn_recursive() {
+    let mut app = build_app();
+    app.add_systems(Startup, spawn_nested_world)
+        .add_ggrs_plugin(
+            GgrsPlugin::<GgrsConfig>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .register_rollback_component::<Root>()
+                .register_rollback_component::<Leaf>(),
+        )
+        .add_systems(GgrsSchedule, despawn_root_system);
+
+    run_frames(&mut app, 2);
+
+    let mut leaves = app.world.query_filtered::<&Parent, With<Leaf>>();
+    let middles: Vec<Entity> = leaves.iter(&app.world).map(Parent::get).collect();
+    assert_eq!(middles.len(), 2);
+    assert_eq!(middles[0], middles[1], "leaves have different parents");
+    assert_eq!(app.world.get::<Children>(middles[0]).unwrap().len(), 2);
+
+    app.world
+        .resource_mut::<Events<TriggerEvent>>()
+        .send(TriggerEvent);
+
+    run_frames(&mut app, 5);
+
+    let mut rollback_entities = app.world.query_filtered::<(), With<Rollback>>();
+    assert_eq!(
+        rollback_entities.iter(&app.world).count(),
+        0,
+        "entities of the despawned hierarchy still exist"
+    );
+}
@@ -0,0 +1,115 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GgrsConfig;
+impl Config for GgrsConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+const HISTORY_FRAMES: usize = 4;
+
+/// The number of times the input system ran, not rolled back.
+#[derive(Resource, Default)]
+struct InputCount(u8);
+
+/// The inputs of every frame, as seen in its first simulation, not rolled back.
+#[derive(Resource, Default)]
+struct Inputs(HashMap<i32, u8>);
+
+/// The number of snapshots loaded, not rolled back.
+#[derive(Resource, Default)]
+struct Loads(usize);
+
+/// Gives every frame a different input.
+fn input_system(_: In<PlayerHandle>, mut count: ResMut<InputCount>) -> u8 {
+    count.0 = count.0.wrapping_add(1);
+    count.0
+}
+
+/// Checks that the history ends with the inputs of `frame`, followed by the frames before it.
+fn check_history(history: &InputHistory<GgrsConfig>, inputs: &Inputs, frame: i32) {
+    let expected = (frame + 1).clamp(0, HISTORY_FRAMES as i32) as usize;
+    assert_eq!(
+        history.len(),
+        expected,
+        "wrong history length in frame {frame}"
+    );
+    for frames_ago in 0..history.len() {
+        let input = history.frame(frames_ago).unwrap()[0].0;
+        let earlier = frame - frames_ago as i32;
+        assert_eq!(
+            Some(&input),
+            inputs.0.get(&earlier),
+            "wrong input of frame {earlier} in the history of frame {frame}"
+        );
+    }
+}
+
+fn record_system(
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    player_inputs: Res<PlayerInputs<GgrsConfig>>,
+    history: Res<InputHistory<GgrsConfig>>,
+    mut inputs: ResMut<Inputs>,
+) {
+    let input = *inputs.0.entry(**frame).or_insert(player_inputs[0].0);
+    assert_eq!(
+        input, player_inputs[0].0,
+        "frame {} resimulated with other inputs",
+        **frame
+    );
+    check_history(&history, &inputs, **frame);
+}
+
+/// A snapshot is taken before its frame is advanced, so the history ends with the previous frame.
+fn check_loaded_history(
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    history: Res<InputHistory<GgrsConfig>>,
+    inputs: Res<Inputs>,
+    mut loads: ResMut<Loads>,
+) {
+    loads.0 += 1;
+    check_history(&history, &inputs, **frame - 1);
+}
+
+/// Makes sure that the input history always holds the inputs of the frames before the current one,
+/// both while frames are simulated again and right after a snapshot has been loaded.
+#[test]
+fn input_history_follows_rollbacks() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<InputCount>()
+        .init_resource::<Inputs>()
+        .init_resource::<Loads>()
+        .insert_resource(Session::SyncTest(
+            SessionBuilder::<GgrsConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .add_ggrs_plugin(
+            GgrsPlugin::<GgrsConfig>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .with_input_history(HISTORY_FRAMES),
+        )
+        .add_systems(GgrsSchedule, record_system)
+        .add_systems(PostLoad, check_loaded_history);
+
+    while app.world.resource::<Inputs>().0.len() < 12 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    assert!(
+        app.world.resource::<Loads>().0 > 0,
+        "no snapshot was loaded"
+    );
+}
@@ -0,0 +1,245 @@
+use bevy::{
+    ecs::{query::ReadOnlyWorldQuery, schedule::ScheduleLabel},
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use std::marker::PhantomData;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct ConfigA;
+impl Config for ConfigA {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+pub struct ConfigB;
+impl Config for ConfigB {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// The rollback schedule of the second instance.
+#[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
+struct ScheduleB;
+
+/// The load hook of the second instance.
+#[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
+struct PostLoadB;
+
+/// The scope marker of the entities of the second instance.
+#[derive(Reflect, Component, Default)]
+#[reflect(Component)]
+struct ScopeB;
+
+#[derive(Reflect, Component, Default)]
+#[reflect(Component)]
+struct Value(i32);
+
+/// Counts the frames of its instance in its `Value`, spawned before the first frame.
+#[derive(Component)]
+struct Counter;
+
+/// Spawned in `SPAWN_FRAME`, loses its `Value` in `REMOVE_FRAME` and is despawned in
+/// `DESPAWN_FRAME`.
+#[derive(Component)]
+struct Temporary;
+
+const SPAWN_FRAME: i32 = 2;
+const REMOVE_FRAME: i32 = 4;
+const DESPAWN_FRAME: i32 = 6;
+
+/// What an instance observed in every simulation of every frame, not rolled back.
+#[derive(Resource)]
+struct Record<T: Config> {
+    /// The number of removals reported in every simulation of every frame.
+    removals: HashMap<i32, Vec<usize>>,
+    /// The rollback ids of all entities seen by the instance.
+    ids: HashSet<u32>,
+    /// The number of snapshots loaded by the instance.
+    loads: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Config> Default for Record<T> {
+    fn default() -> Self {
+        Self {
+            removals: HashMap::default(),
+            ids: HashSet::default(),
+            loads: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn spawn_world(mut commands: Commands) {
+    commands
+        .spawn((Value(0), RollbackStopwatch::new(), Counter))
+        .add_rollback();
+    commands
+        .spawn((Value(0), RollbackStopwatch::new(), Counter, ScopeB))
+        .add_rollback();
+}
+
+/// Runs the same simulation in both instances. `S` is the scope marker spawned with new entities
+/// and `F` selects the entities of the instance.
+fn simulate<T: Config, S: Bundle + Default, F: ReadOnlyWorldQuery + 'static>(
+    mut commands: Commands,
+    frame: Res<RollbackFrameCount<T>>,
+    mut counters: Query<(&mut Value, &RollbackStopwatch), (With<Counter>, F)>,
+    temporaries: Query<Entity, (With<Temporary>, With<Rollback>, F)>,
+    rollbacks: Query<&Rollback, F>,
+    removed: RollbackRemovedComponents<T, Value>,
+    mut record: ResMut<Record<T>>,
+) {
+    let frame = **frame;
+    let (mut value, stopwatch) = counters.single_mut();
+    assert_eq!(value.0, frame, "wrong counter loaded in frame {frame}");
+    assert_eq!(
+        stopwatch.elapsed(),
+        frame as u32 + 1,
+        "stopwatch ticked by the wrong instances in frame {frame}"
+    );
+    value.0 += 1;
+
+    record.ids.extend(rollbacks.iter().map(Rollback::id));
+    let removals = removed.iter().count();
+    record.removals.entry(frame).or_default().push(removals);
+
+    match frame {
+        SPAWN_FRAME => {
+            commands.spawn_rollback((Value(0), Temporary, S::default()));
+        }
+        REMOVE_FRAME => {
+            commands.entity(temporaries.single()).remove::<Value>();
+        }
+        DESPAWN_FRAME => commands.entity(temporaries.single()).despawn_rollback(),
+        _ => {}
+    }
+}
+
+/// Makes sure that the counter of an instance is only touched by that instance, also when the other
+/// instance loads a snapshot after it.
+fn check_counter<T: Config, F: ReadOnlyWorldQuery + 'static>(
+    frame: Res<RollbackFrameCount<T>>,
+    update_info: Res<GgrsUpdateInfo<T>>,
+    counters: Query<&Value, (With<Counter>, F)>,
+) {
+    if update_info.frames_advanced() > 0 {
+        assert_eq!(counters.single().0, **frame + 1);
+    }
+}
+
+/// Makes sure that the load hook of an instance only runs for its own snapshots. The other
+/// instance has already advanced its counter past its frame.
+fn check_loaded<T: Config, F: ReadOnlyWorldQuery + 'static>(
+    frame: Res<RollbackFrameCount<T>>,
+    counters: Query<&Value, (With<Counter>, F)>,
+    mut record: ResMut<Record<T>>,
+) {
+    assert_eq!(
+        counters.single().0,
+        **frame,
+        "load hook ran for the other instance"
+    );
+    record.loads += 1;
+}
+
+fn check_record<T: Config>(record: &Record<T>) {
+    assert_eq!(record.ids, HashSet::from_iter([0, 1]));
+    assert!(record.loads > 0, "no snapshot was loaded");
+
+    let simulations = &record.removals[&(REMOVE_FRAME + 1)];
+    assert!(simulations.len() > 1, "the frame was not resimulated");
+    assert!(simulations.iter().all(|&removals| removals == 1));
+    for (frame, simulations) in record.removals.iter() {
+        if *frame != REMOVE_FRAME + 1 {
+            assert!(
+                simulations.iter().all(|&removals| removals == 0),
+                "removal reported in frame {frame}"
+            );
+        }
+    }
+}
+
+fn synctest_session<T: Config>() -> Session<T> {
+    Session::SyncTest(
+        SessionBuilder::<T>::new()
+            .with_num_players(1)
+            .with_check_distance(2)
+            .add_player(PlayerType::Local, 0)
+            .unwrap()
+            .start_synctest_session()
+            .unwrap(),
+    )
+}
+
+/// Makes sure that two plugin instances with their own schedules and scope only save, load, tick,
+/// number and despawn their own entities, and only report their own removals.
+#[test]
+fn instances_are_independent() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<Record<ConfigA>>()
+        .init_resource::<Record<ConfigB>>()
+        .add_systems(Startup, spawn_world)
+        .insert_resource(synctest_session::<ConfigA>())
+        .insert_resource(synctest_session::<ConfigB>())
+        .add_ggrs_plugin(
+            GgrsPlugin::<ConfigA>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .register_rollback_component::<Value>(),
+        )
+        .add_ggrs_plugin(
+            GgrsPlugin::<ConfigB>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .with_schedule(ScheduleB)
+                .with_post_load_schedule(PostLoadB)
+                .with_scope::<ScopeB>()
+                .register_rollback_component::<Value>(),
+        )
+        .add_systems(GgrsSchedule, simulate::<ConfigA, (), Without<ScopeB>>)
+        .add_systems(ScheduleB, simulate::<ConfigB, ScopeB, With<ScopeB>>)
+        .add_systems(PostLoad, check_loaded::<ConfigA, Without<ScopeB>>)
+        .add_systems(PostLoadB, check_loaded::<ConfigB, With<ScopeB>>)
+        .add_systems(
+            Update,
+            (
+                check_counter::<ConfigA, Without<ScopeB>>,
+                check_counter::<ConfigB, With<ScopeB>>,
+            ),
+        );
+
+    app.update();
+    let mut counters = app.world.query_filtered::<Entity, With<Counter>>();
+    let counters: Vec<Entity> = counters.iter(&app.world).collect();
+    assert_eq!(counters.len(), 2);
+
+    // the default prediction window is 8 frames, after which the despawns are confirmed
+    while app.world.resource::<Record<ConfigA>>().removals.len() < DESPAWN_FRAME as usize + 20
+        || app.world.resource::<Record<ConfigB>>().removals.len() < DESPAWN_FRAME as usize + 20
+    {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    check_record(app.world.resource::<Record<ConfigA>>());
+    check_record(app.world.resource::<Record<ConfigB>>());
+
+    // neither instance despawned or respawned the counter of the other one
+    for counter in counters {
+        assert!(app.world.get_entity(counter).is_some());
+    }
+    let mut temporaries = app.world.query_filtered::<(), With<Temporary>>();
+    assert_eq!(temporaries.iter(&app.world).count(), 0);
+}
@@ -0,0 +1,116 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GgrsConfig;
+impl Config for GgrsConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// The marker of the pool.
+#[derive(Component)]
+struct Bullet;
+
+/// The frame in which a bullet was acquired.
+#[derive(Reflect, Component, Default)]
+#[reflect(Component)]
+struct Fired(i32);
+
+/// The rollback ids and `Fired` frames of the active bullets in every simulation of every frame,
+/// not rolled back.
+#[derive(Resource, Default)]
+struct Active(HashMap<i32, Vec<Vec<(u32, i32)>>>);
+
+const RELEASE_FRAME: i32 = 5;
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn spawn_pool(mut commands: Commands) {
+    for _ in 0..3 {
+        commands.spawn_pooled(Bullet);
+    }
+}
+
+fn pool_system(
+    mut commands: Commands,
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    bullets: Query<(Entity, &Rollback, &Fired), (With<Bullet>, With<PoolActive>)>,
+    mut active: ResMut<Active>,
+) {
+    let mut ids: Vec<(u32, i32)> = bullets
+        .iter()
+        .map(|(_, rollback, fired)| (rollback.id(), fired.0))
+        .collect();
+    ids.sort();
+    active.0.entry(**frame).or_default().push(ids);
+
+    match **frame {
+        2 | 3 | 6 => commands.acquire_pooled::<Bullet, _>(Fired(**frame)),
+        RELEASE_FRAME => {
+            for (entity, rollback, _) in bullets.iter() {
+                if rollback.id() == 0 {
+                    commands.entity(entity).release_to_pool();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Makes sure that the free pooled entity with the lowest rollback id is acquired, and that
+/// acquiring and releasing pooled entities is rolled back.
+#[test]
+fn pools_follow_rollbacks() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<Active>()
+        .add_systems(Startup, spawn_pool)
+        .insert_resource(Session::SyncTest(
+            SessionBuilder::<GgrsConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .add_ggrs_plugin(
+            GgrsPlugin::<GgrsConfig>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .register_rollback_component::<Fired>(),
+        )
+        .add_systems(GgrsSchedule, pool_system);
+
+    while app.world.resource::<Active>().0.len() < 12 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let active = &app.world.resource::<Active>().0;
+    for (frame, simulations) in active.iter() {
+        // commands of a frame are visible in the next one
+        let expected: &[(u32, i32)] = match *frame {
+            frame if frame <= 2 => &[],
+            3 => &[(0, 2)],
+            4 | RELEASE_FRAME => &[(0, 2), (1, 3)],
+            6 => &[(1, 3)],
+            _ => &[(0, 6), (1, 3)],
+        };
+        for ids in simulations {
+            assert_eq!(ids, expected, "wrong active bullets in frame {frame}");
+        }
+    }
+    for frame in 3..=7 {
+        assert!(
+            active[&frame].len() > 1,
+            "frame {frame} was not resimulated"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GgrsConfig;
+impl Config for GgrsConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// Despawned in `DESPAWN_FRAME`, and respawned by rollbacks to earlier frames.
+#[derive(Reflect, Component, Default)]
+#[reflect(Component)]
+struct Target;
+
+/// Points at the target. It is rolled back without `ReflectMapEntities`, so the entity is restored
+/// as it was saved.
+#[derive(Reflect, Component)]
+#[reflect(Component)]
+struct Pointer(Entity);
+
+impl Default for Pointer {
+    fn default() -> Self {
+        Self(Entity::PLACEHOLDER)
+    }
+}
+
+/// The target, stored outside of the rollback state.
+#[derive(Resource)]
+struct Stored(Entity);
+
+/// Whether the target has been despawned in any frame, not rolled back.
+#[derive(Resource, Default)]
+struct Despawned(bool);
+
+/// The number of snapshots loaded with the target after it has been despawned, not rolled back.
+#[derive(Resource, Default)]
+struct Respawned(usize);
+
+const DESPAWN_FRAME: i32 = 4;
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn spawn_world(mut commands: Commands) {
+    let target = commands.spawn(Target).add_rollback().id();
+    commands.spawn(Pointer(target)).add_rollback();
+    commands.insert_resource(Stored(target));
+}
+
+fn despawn_system(
+    mut commands: Commands,
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    targets: Query<Entity, With<Target>>,
+    mut despawned: ResMut<Despawned>,
+) {
+    if **frame == DESPAWN_FRAME {
+        commands.entity(targets.single()).despawn();
+        despawned.0 = true;
+    }
+}
+
+fn check_loaded(
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    stored: Res<Stored>,
+    despawned: Res<Despawned>,
+    targets: Query<Entity, With<Target>>,
+    pointers: Query<&Pointer>,
+    mut respawned: ResMut<Respawned>,
+) {
+    if **frame > DESPAWN_FRAME {
+        assert!(targets.is_empty(), "despawned target loaded");
+        return;
+    }
+    assert_eq!(
+        targets.single(),
+        stored.0,
+        "target respawned with another id"
+    );
+    assert_eq!(pointers.single().0, stored.0, "pointer lost its target");
+    if despawned.0 {
+        respawned.0 += 1;
+    }
+}
+
+/// Makes sure that entities respawned with preserved ids are still referenced by entities stored in
+/// resources and in components without entity mapping.
+#[test]
+fn preserved_ids_survive_despawns() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<Despawned>()
+        .init_resource::<Respawned>()
+        .add_systems(Startup, spawn_world)
+        .insert_resource(Session::SyncTest(
+            SessionBuilder::<GgrsConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .add_ggrs_plugin(
+            GgrsPlugin::<GgrsConfig>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .with_preserved_entity_ids(true)
+                .register_rollback_component::<Target>()
+                .register_rollback_component::<Pointer>(),
+        )
+        .add_systems(GgrsSchedule, despawn_system)
+        .add_systems(PostLoad, check_loaded);
+
+    while **app.world.resource::<RollbackFrameCount<GgrsConfig>>() < DESPAWN_FRAME + 6 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    assert!(
+        app.world.resource::<Respawned>().0 > 0,
+        "the target was never respawned"
+    );
+    let stored = app.world.resource::<Stored>().0;
+    assert!(app.world.get_entity(stored).is_none());
+}
@@ -0,0 +1,124 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GgrsConfig;
+impl Config for GgrsConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Component, Default)]
+#[reflect(Component)]
+struct Value(i32);
+
+/// Loses its `Value` in every simulation of `REMOVE_FRAME`.
+#[derive(Component)]
+struct Resimulated;
+
+/// Loses its `Value` only in the first simulation of `REMOVE_FRAME`, like after a misprediction.
+#[derive(Component)]
+struct Mispredicted;
+
+const REMOVE_FRAME: i32 = 5;
+
+/// The removals reported in every simulation of every frame, not rolled back.
+#[derive(Resource, Default)]
+struct Reported(HashMap<i32, Vec<Vec<&'static str>>>);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn spawn_world(mut commands: Commands) {
+    commands.spawn((Value(0), Resimulated)).add_rollback();
+    commands.spawn((Value(0), Mispredicted)).add_rollback();
+}
+
+fn report_system(
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    removed: RollbackRemovedComponents<GgrsConfig, Value>,
+    resimulated: Query<(), With<Resimulated>>,
+    mut reported: ResMut<Reported>,
+) {
+    let names = removed
+        .iter()
+        .map(|entity| {
+            if resimulated.contains(entity) {
+                "resimulated"
+            } else {
+                "mispredicted"
+            }
+        })
+        .collect();
+    reported.0.entry(**frame).or_default().push(names);
+}
+
+fn remove_system(
+    mut commands: Commands,
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    reported: Res<Reported>,
+    resimulated: Query<Entity, With<Resimulated>>,
+    mispredicted: Query<Entity, With<Mispredicted>>,
+) {
+    if **frame != REMOVE_FRAME {
+        return;
+    }
+    commands.entity(resimulated.single()).remove::<Value>();
+    let first_simulation = reported.0[&REMOVE_FRAME].len() == 1;
+    if first_simulation {
+        commands.entity(mispredicted.single()).remove::<Value>();
+    }
+}
+
+/// Makes sure that removals are reported once per simulation of the frame after the removal, and
+/// that removals which were rolled back are no longer reported.
+#[test]
+fn removals_follow_rollbacks() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<Reported>()
+        .add_systems(Startup, spawn_world)
+        .insert_resource(Session::SyncTest(
+            SessionBuilder::<GgrsConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .add_ggrs_plugin(
+            GgrsPlugin::<GgrsConfig>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .register_rollback_component::<Value>(),
+        )
+        .add_systems(GgrsSchedule, (report_system, remove_system).chain());
+
+    while app.world.resource::<Reported>().0.len() < 10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let reported = &app.world.resource::<Reported>().0;
+    let simulations = &reported[&(REMOVE_FRAME + 1)];
+    assert!(simulations.len() > 1, "the frame was not resimulated");
+    // the first simulation follows the mispredicted removal
+    assert_eq!(simulations[0], ["resimulated", "mispredicted"]);
+    for names in &simulations[1..] {
+        assert_eq!(names, &["resimulated"]);
+    }
+
+    for (frame, simulations) in reported.iter() {
+        if *frame != REMOVE_FRAME + 1 {
+            assert!(
+                simulations.iter().all(Vec::is_empty),
+                "removal reported in frame {frame}"
+            );
+        }
+    }
+}
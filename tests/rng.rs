@@ -0,0 +1,165 @@
+use bevy::{ecs::system::EntityCommand, prelude::*, utils::HashMap};
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GgrsConfig;
+impl Config for GgrsConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+const SEED: u64 = 0x5eed;
+
+/// The numbers drawn in every simulation of every frame, not rolled back.
+#[derive(Resource, Default)]
+struct Drawn(HashMap<i32, Vec<u64>>);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn draw_system(
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    mut rng: ResMut<RollbackRng>,
+    mut drawn: ResMut<Drawn>,
+) {
+    drawn.0.entry(**frame).or_default().push(rng.next_u64());
+}
+
+/// Draws a few numbers of every kind, as bits so floats can be compared exactly.
+fn sequence(rng: &mut RollbackRng) -> Vec<u64> {
+    let mut sequence = Vec::new();
+    for _ in 0..8 {
+        sequence.push(rng.next_u64());
+        sequence.push(rng.next_u32() as u64);
+        sequence.push(rng.next_bool() as u64);
+        sequence.push(rng.next_f32().to_bits() as u64);
+        sequence.push(rng.next_f64().to_bits());
+    }
+    sequence
+}
+
+fn rollback_ids(count: usize) -> Vec<Rollback> {
+    let mut world = World::new();
+    (0..count)
+        .map(|_| {
+            let entity = world.spawn_empty().id();
+            AddRollbackCommand.apply(entity, &mut world);
+            *world.get::<Rollback>(entity).unwrap()
+        })
+        .collect()
+}
+
+/// Makes sure that generators with the same seed draw the same numbers.
+#[test]
+fn sequences_are_deterministic() {
+    let mut rng = RollbackRng::new(SEED);
+    let first = sequence(&mut rng);
+    assert_eq!(first, sequence(&mut RollbackRng::new(SEED)));
+    assert_ne!(first, sequence(&mut RollbackRng::new(SEED + 1)));
+    assert_ne!(first, sequence(&mut rng), "the generator repeats itself");
+    assert_eq!(RollbackRng::new(SEED).seed(), SEED);
+
+    for value in first.iter().skip(3).step_by(5) {
+        let value = f32::from_bits(*value as u32);
+        assert!((0.0..1.0).contains(&value));
+    }
+    for value in first.iter().skip(4).step_by(5) {
+        let value = f64::from_bits(*value);
+        assert!((0.0..1.0).contains(&value));
+    }
+}
+
+/// Makes sure that entity streams only depend on the seed and the rollback id.
+#[test]
+fn streams_are_deterministic() {
+    let ids = rollback_ids(2);
+    let mut rng = RollbackRng::new(SEED);
+    let stream = rng.stream(&ids[0]);
+    // drawing from the generator doesn't change its streams
+    rng.next_u64();
+    assert_eq!(rng.stream(&ids[0]), stream);
+    assert_eq!(RollbackRng::new(SEED).stream(&ids[0]), stream);
+
+    let mut stream = stream;
+    let first = sequence(&mut stream);
+    assert_eq!(first, sequence(&mut RollbackRng::new(SEED).stream(&ids[0])));
+    assert_ne!(first, sequence(&mut rng.stream(&ids[1])));
+    assert_ne!(
+        first,
+        sequence(&mut RollbackRng::new(SEED + 1).stream(&ids[0]))
+    );
+}
+
+/// Makes sure that ranges are deterministic and never leave the range.
+#[test]
+fn ranges_are_deterministic() {
+    let ranges_u32 = [0..1, 0..7, 10..1_000, u32::MAX - 3..u32::MAX, 0..u32::MAX];
+    let ranges_i32 = [-5..5, -100..-50, i32::MIN..i32::MAX, i32::MAX - 1..i32::MAX];
+
+    let draw = |rng: &mut RollbackRng| {
+        let mut values = Vec::new();
+        for _ in 0..100 {
+            for range in ranges_u32.iter().cloned() {
+                let value = rng.range_u32(range.clone());
+                assert!(range.contains(&value), "{value} not in {range:?}");
+                values.push(value as i64);
+            }
+            for range in ranges_i32.iter().cloned() {
+                let value = rng.range_i32(range.clone());
+                assert!(range.contains(&value), "{value} not in {range:?}");
+                values.push(value as i64);
+            }
+        }
+        values
+    };
+
+    let first = draw(&mut RollbackRng::new(SEED));
+    assert_eq!(first, draw(&mut RollbackRng::new(SEED)));
+    assert_ne!(first, draw(&mut RollbackRng::new(SEED + 1)));
+}
+
+/// Makes sure that the resource added by `with_rng()` is rolled back, so every simulation of a
+/// frame draws the same number.
+#[test]
+fn rng_resource_follows_rollbacks() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<Drawn>()
+        .insert_resource(Session::SyncTest(
+            SessionBuilder::<GgrsConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .add_ggrs_plugin(
+            GgrsPlugin::<GgrsConfig>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .with_rng(SEED),
+        )
+        .add_systems(GgrsSchedule, draw_system);
+
+    while app.world.resource::<Drawn>().0.len() < 10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let drawn = &app.world.resource::<Drawn>().0;
+    let mut rng = RollbackRng::new(SEED);
+    for frame in 0..10 {
+        let expected = rng.next_u64();
+        let simulations = &drawn[&frame];
+        assert!(
+            simulations.iter().all(|&value| value == expected),
+            "frame {frame} drew {simulations:?}, expected {expected}"
+        );
+    }
+    assert!(drawn[&5].len() > 1, "the frame was not resimulated");
+}
@@ -0,0 +1,164 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GgrsConfig;
+impl Config for GgrsConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(States, Default, Debug, Clone, Hash, PartialEq, Eq)]
+enum Phase {
+    #[default]
+    Waiting,
+    Playing,
+}
+
+/// The state is set to `Playing` in this frame, and applied at the start of the next one.
+const PLAY_FRAME: i32 = 4;
+/// The state is set back to `Waiting` in this frame, and applied at the start of the next one.
+const WAIT_FRAME: i32 = 8;
+
+/// How often every frame has been simulated, not rolled back.
+#[derive(Resource, Default)]
+struct Simulations(HashMap<i32, usize>);
+
+/// How often every transition schedule ran in every frame, not rolled back.
+#[derive(Resource, Default)]
+struct Transitions(HashMap<(i32, &'static str), usize>);
+
+/// The frames that have been loaded, not rolled back.
+#[derive(Resource, Default)]
+struct Loaded(HashSet<i32>);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn switch_phase(
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    mut simulations: ResMut<Simulations>,
+    mut next_phase: ResMut<NextState<Phase>>,
+) {
+    *simulations.0.entry(**frame).or_default() += 1;
+    match **frame {
+        PLAY_FRAME => next_phase.set(Phase::Playing),
+        WAIT_FRAME => next_phase.set(Phase::Waiting),
+        _ => {}
+    }
+}
+
+fn record_transition(
+    name: &'static str,
+) -> impl Fn(Res<RollbackFrameCount<GgrsConfig>>, ResMut<Transitions>) {
+    move |frame, mut transitions| {
+        *transitions.0.entry((**frame, name)).or_default() += 1;
+    }
+}
+
+/// Checks that the loaded state is the one of the loaded frame. A snapshot is taken before the
+/// frame is advanced, so transitions are still pending in the snapshot of the frame after they
+/// were queued.
+fn check_loaded_state(
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    phase: Res<State<Phase>>,
+    next_phase: Res<NextState<Phase>>,
+    mut loaded: ResMut<Loaded>,
+) {
+    let frame = **frame;
+    loaded.0.insert(frame);
+
+    let expected = if frame > PLAY_FRAME + 1 && frame <= WAIT_FRAME + 1 {
+        Phase::Playing
+    } else {
+        Phase::Waiting
+    };
+    assert_eq!(
+        *phase.get(),
+        expected,
+        "wrong state loaded for frame {frame}"
+    );
+
+    let expected_next = match frame {
+        frame if frame == PLAY_FRAME + 1 => Some(Phase::Playing),
+        frame if frame == WAIT_FRAME + 1 => Some(Phase::Waiting),
+        _ => None,
+    };
+    assert_eq!(
+        next_phase.0, expected_next,
+        "wrong pending transition loaded for frame {frame}"
+    );
+}
+
+/// Makes sure that state transitions queued in the rollback schedule run their transition
+/// schedules in exactly the same frames when they are simulated again, and that loading a snapshot
+/// restores the state of that frame.
+#[test]
+fn state_transitions_follow_rollbacks() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<Simulations>()
+        .init_resource::<Transitions>()
+        .init_resource::<Loaded>()
+        .insert_resource(Session::SyncTest(
+            SessionBuilder::<GgrsConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .add_ggrs_plugin(
+            GgrsPlugin::<GgrsConfig>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system)
+                .register_rollback_state::<Phase>(),
+        )
+        .add_systems(GgrsSchedule, switch_phase)
+        .add_systems(OnEnter(Phase::Waiting), record_transition("enter waiting"))
+        .add_systems(OnExit(Phase::Waiting), record_transition("exit waiting"))
+        .add_systems(OnEnter(Phase::Playing), record_transition("enter playing"))
+        .add_systems(OnExit(Phase::Playing), record_transition("exit playing"))
+        .add_systems(PostLoad, check_loaded_state);
+
+    while app.world.resource::<Simulations>().0.len() < 14 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let loaded = &app.world.resource::<Loaded>().0;
+    for frame in PLAY_FRAME..=WAIT_FRAME + 1 {
+        assert!(loaded.contains(&frame), "frame {frame} was never loaded");
+    }
+
+    // every simulation of a frame runs the same transitions
+    let simulations = &app.world.resource::<Simulations>().0;
+    let expected = [
+        (0, "enter waiting"),
+        (PLAY_FRAME + 1, "exit waiting"),
+        (PLAY_FRAME + 1, "enter playing"),
+        (WAIT_FRAME + 1, "exit playing"),
+        (WAIT_FRAME + 1, "enter waiting"),
+    ];
+    let transitions = &app.world.resource::<Transitions>().0;
+    assert_eq!(transitions.len(), expected.len(), "{transitions:?}");
+    for (frame, name) in expected {
+        // the first frame is not necessarily rolled back to
+        if frame > 0 {
+            assert!(simulations[&frame] > 1, "frame {frame} was not resimulated");
+        }
+        assert_eq!(
+            transitions.get(&(frame, name)),
+            Some(&simulations[&frame]),
+            "{name} in frame {frame}"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GgrsConfig;
+impl Config for GgrsConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// Which timer an entity carries, not rolled back.
+#[derive(Component, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+enum Kind {
+    /// Finishes once after `ONCE_FRAMES`.
+    Once,
+    /// Wraps around every `REPEATING_FRAMES`.
+    Repeating,
+    /// Wraps around every two frames until it is paused in `PAUSE_FRAME`.
+    Paused,
+}
+
+const ONCE_FRAMES: u32 = 3;
+const REPEATING_FRAMES: u32 = 3;
+const PAUSE_FRAME: i32 = 4;
+
+/// The elapsed frames and finished flags of a timer.
+type TimerState = (u32, bool, bool);
+
+/// The state of every timer in every simulation of every frame, not rolled back.
+#[derive(Resource, Default)]
+struct Record(HashMap<(i32, Kind), Vec<TimerState>>);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn spawn_world(mut commands: Commands) {
+    commands
+        .spawn((Kind::Once, RollbackTimer::new(ONCE_FRAMES, TimerMode::Once)))
+        .add_rollback();
+    commands
+        .spawn((
+            Kind::Repeating,
+            RollbackTimer::new(REPEATING_FRAMES, TimerMode::Repeating),
+        ))
+        .add_rollback();
+    commands
+        .spawn((Kind::Paused, RollbackTimer::new(2, TimerMode::Repeating)))
+        .add_rollback();
+}
+
+fn record_system(
+    frame: Res<RollbackFrameCount<GgrsConfig>>,
+    mut timers: Query<(&Kind, &mut RollbackTimer)>,
+    mut record: ResMut<Record>,
+) {
+    for (kind, mut timer) in timers.iter_mut() {
+        let state = (timer.elapsed(), timer.finished(), timer.just_finished());
+        record.0.entry((**frame, *kind)).or_default().push(state);
+        if *kind == Kind::Paused && **frame == PAUSE_FRAME {
+            timer.pause();
+        }
+    }
+}
+
+/// The state a timer should have in the rollback schedule of the given frame. Timers are ticked
+/// at the start of every frame, so they have already been ticked once in the first frame.
+fn expected(kind: Kind, frame: i32) -> TimerState {
+    let ticks = frame as u32 + 1;
+    match kind {
+        Kind::Once => (
+            ticks.min(ONCE_FRAMES),
+            ticks >= ONCE_FRAMES,
+            ticks == ONCE_FRAMES,
+        ),
+        Kind::Repeating => {
+            let elapsed = ticks % REPEATING_FRAMES;
+            (elapsed, elapsed == 0, elapsed == 0)
+        }
+        Kind::Paused if frame <= PAUSE_FRAME => {
+            let elapsed = ticks % 2;
+            (elapsed, elapsed == 0, elapsed == 0)
+        }
+        // paused after the tick of `PAUSE_FRAME`, so it keeps that elapsed frame
+        Kind::Paused => ((PAUSE_FRAME as u32 + 1) % 2, false, false),
+    }
+}
+
+/// Makes sure that once, repeating and paused timers are ticked exactly once per frame, and are
+/// identical in every simulation of a frame.
+#[test]
+fn timers_follow_rollbacks() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<Record>()
+        .add_systems(Startup, spawn_world)
+        .insert_resource(Session::SyncTest(
+            SessionBuilder::<GgrsConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .add_ggrs_plugin(
+            GgrsPlugin::<GgrsConfig>::new()
+                .with_update_frequency(60)
+                .with_input_system(input_system),
+        )
+        .add_systems(GgrsSchedule, record_system);
+
+    while **app.world.resource::<RollbackFrameCount<GgrsConfig>>() < PAUSE_FRAME + 6 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let record = &app.world.resource::<Record>().0;
+    for frame in 0..PAUSE_FRAME + 4 {
+        for kind in [Kind::Once, Kind::Repeating, Kind::Paused] {
+            let simulations = &record[&(frame, kind)];
+            let expected = expected(kind, frame);
+            assert!(
+                simulations.iter().all(|&state| state == expected),
+                "{kind:?} timer in frame {frame} was {simulations:?}, expected {expected:?}"
+            );
+        }
+    }
+    assert!(
+        record[&(PAUSE_FRAME + 1, Kind::Paused)].len() > 1,
+        "the frame was not resimulated"
+    );
+}